@@ -8,7 +8,18 @@ struct FragmentInput {
 };
 
 struct Push {
+    // the region of the target shown by this view, y-up like the target
+    offset: vec2<f32>,
+    scale: vec2<f32>,
     flags: u32,
+    _pad: u32,
+};
+
+struct Exposure {
+    max: f32,
+    mean: f32,
+    percentile: f32,
+    exposure: f32,
 };
 
 @group(0)
@@ -19,18 +30,53 @@ var texture_t: texture_2d<f32>;
 @binding(1)
 var texture_s: sampler;
 
+@group(0)
+@binding(2)
+var<uniform> exposure: Exposure;
+
+// push constants, or a uniform buffer without them
+//!ifdef PARAMS_UNIFORM
+@group(1)
+@binding(0)
+var<uniform> push: Push;
+//!else
 var<push_constant> push: Push;
+//!endif
 
 @vertex
 fn vs_main(vin: VertexInput) -> FragmentInput {
     let uv = vec2<f32>(f32(vin.vi % 2u), f32(vin.vi / 2u));
 
     var fin: FragmentInput;
-    fin.uv = vec2<f32>(uv.x, uv.y);
+    // uv.y = 0 is the bottom of the view and row 0 of the target is the bottom,
+    // Region::to_target mirrors this
+    fin.uv = push.offset + uv * push.scale;
     fin.pos = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
     return fin;
 }
 
+fn exposed(uv: vec2<f32>) -> f32 {
+    return min(textureSampleLevel(texture_t, texture_s, uv, 0.0).r * exposure.exposure, 1.0);
+}
+
+// bilinear when upscaling, a box filter over the
+// pixel footprint when downscaling (render_scale > 1)
+fn resample(uv: vec2<f32>) -> f32 {
+    let dim = vec2<f32>(textureDimensions(texture_t));
+    let footprint = fwidth(uv);
+    let taps = vec2<i32>(clamp(ceil(footprint * dim), vec2<f32>(1.0), vec2<f32>(4.0)));
+
+    var sum = 0.0;
+    for (var y = 0; y < taps.y; y += 1) {
+        for (var x = 0; x < taps.x; x += 1) {
+            let offset = (vec2<f32>(f32(x), f32(y)) + 0.5) / vec2<f32>(taps) - 0.5;
+            sum += exposed(uv + offset * footprint);
+        }
+    }
+
+    return sum / f32(taps.x * taps.y);
+}
+
 @fragment
 fn fs_main(fin: FragmentInput) -> @location(0) vec4<f32> {
     if push.flags == 0u {
@@ -46,7 +92,7 @@ fn fs_main(fin: FragmentInput) -> @location(0) vec4<f32> {
             + textureSample(texture_t, texture_s, fin.uv + vec2f(0.0, -o.y)).r * 0.11111111
             + textureSample(texture_t, texture_s, fin.uv + vec2f(-o.x,-o.y)).r * 0.11111111; */
 
-        let c = textureSample(texture_t, texture_s, fin.uv).r;
+        let c = resample(fin.uv);
 
         return vec4<f32>(0.0, fin.uv.x * fin.uv.y, fin.uv.y, c);
     } else {
@@ -0,0 +1,140 @@
+// log2 histogram of the accumulation texture,
+// bin 0 holds the empty texels
+const BINS: u32 = 256u;
+const MIN_LOG2: f32 = -16.0;
+const RANGE_LOG2: f32 = 24.0;
+
+struct ExposurePush {
+    dt: f32,
+    speed: f32,
+    percentile: f32,
+    manual: f32,
+    flags: u32,
+};
+
+struct Histogram {
+    max: atomic<u32>,
+    bins: array<atomic<u32>, 256>,
+};
+
+struct Stats {
+    max: f32,
+    mean: f32,
+    percentile: f32,
+    exposure: f32,
+};
+
+// push constants, or a uniform buffer without them
+//!ifdef PARAMS_UNIFORM
+@group(1)
+@binding(0)
+var<uniform> push: ExposurePush;
+//!else
+var<push_constant> push: ExposurePush;
+//!endif
+
+@group(0)
+@binding(0)
+var texture: texture_2d<f32>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> histogram: Histogram;
+
+@group(0)
+@binding(2)
+var<storage, read_write> stats: Stats;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> local_max: atomic<u32>;
+var<workgroup> counts: array<u32, 256>;
+
+fn bin_of(v: f32) -> u32 {
+    if v < exp2(MIN_LOG2) {
+        return 0u;
+    }
+
+    let t = (log2(v) - MIN_LOG2) / RANGE_LOG2;
+    return u32(clamp(t * f32(BINS - 2u) + 1.0, 1.0, f32(BINS - 1u)));
+}
+
+fn value_of(bin: u32) -> f32 {
+    if bin == 0u {
+        return 0.0;
+    }
+
+    return exp2((f32(bin) - 0.5) / f32(BINS - 2u) * RANGE_LOG2 + MIN_LOG2);
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+fn cs_main_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) li: u32,
+) {
+    atomicStore(&local_bins[li], 0u);
+    if li == 0u {
+        atomicStore(&local_max, 0u);
+    }
+    workgroupBarrier();
+
+    if all(id.xy < textureDimensions(texture)) {
+        // positive floats sort the same way as their bit patterns
+        let v = max(textureLoad(texture, id.xy, 0).r, 0.0);
+        atomicAdd(&local_bins[bin_of(v)], 1u);
+        atomicMax(&local_max, bitcast<u32>(v));
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram.bins[li], atomicLoad(&local_bins[li]));
+    if li == 0u {
+        atomicMax(&histogram.max, atomicLoad(&local_max));
+    }
+}
+
+@compute
+@workgroup_size(256, 1, 1)
+fn cs_main_resolve(@builtin(local_invocation_index) li: u32) {
+    counts[li] = atomicExchange(&histogram.bins[li], 0u);
+    workgroupBarrier();
+
+    if li != 0u {
+        return;
+    }
+
+    var total = 0u;
+    var sum = 0.0;
+    for (var i = 0u; i < BINS; i += 1u) {
+        total += counts[i];
+        sum += f32(counts[i]) * value_of(i);
+    }
+    let mean = sum / f32(max(total, 1u));
+
+    // the percentile ignores the empty texels
+    let lit = total - counts[0];
+    let threshold = u32(ceil(f32(lit) * push.percentile));
+    var percentile = 0.0;
+    var seen = 0u;
+    for (var i = 1u; i < BINS && lit != 0u; i += 1u) {
+        seen += counts[i];
+        if seen >= threshold {
+            percentile = value_of(i);
+            break;
+        }
+    }
+
+    let max_value = bitcast<f32>(atomicExchange(&histogram.max, 0u));
+
+    var target_exposure = push.manual;
+    if (push.flags & 1u) != 0u && percentile > 0.0 {
+        target_exposure = 1.0 / percentile;
+    }
+
+    var exposure = stats.exposure;
+    if exposure <= 0.0 {
+        exposure = target_exposure;
+    }
+    exposure = mix(exposure, target_exposure, 1.0 - exp(-push.dt * push.speed));
+
+    stats = Stats(max_value, mean, percentile, exposure);
+}
@@ -0,0 +1,183 @@
+struct VertexInput {
+    @builtin(vertex_index) vi: u32,
+};
+
+struct FragmentInput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct Push {
+    // 1 / source size
+    texel: vec2<f32>,
+    dir: vec2<f32>,
+    // pass specific parameters
+    params: vec4<f32>,
+};
+
+@group(0)
+@binding(0)
+var source_t: texture_2d<f32>;
+
+@group(0)
+@binding(1)
+var source_s: sampler;
+
+// only bound for the bloom composite pass
+@group(0)
+@binding(2)
+var bloom_t: texture_2d<f32>;
+
+// push constants, or a uniform buffer without them
+//!ifdef PARAMS_UNIFORM
+@group(1)
+@binding(0)
+var<uniform> push: Push;
+//!else
+var<push_constant> push: Push;
+//!endif
+
+@vertex
+fn vs_main(vin: VertexInput) -> FragmentInput {
+    let p = vec2<f32>(f32(vin.vi % 2u), f32(vin.vi / 2u)) * 2.0 - 1.0;
+
+    var fin: FragmentInput;
+    fin.uv = vec2<f32>(p.x * 0.5 + 0.5, 0.5 - p.y * 0.5);
+    fin.pos = vec4<f32>(p, 0.0, 1.0);
+    return fin;
+}
+
+fn sample(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(source_t, source_s, uv, 0.0);
+}
+
+// params: (sigma, _, _, _)
+@fragment
+fn fs_blur(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let sigma = max(push.params.x, 0.001);
+    let radius = min(i32(ceil(sigma * 3.0)), 32);
+
+    var sum = vec4<f32>(0.0);
+    var weight = 0.0;
+    for (var i = -radius; i <= radius; i += 1) {
+        let w = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        sum += sample(fin.uv + push.dir * push.texel * f32(i)) * w;
+        weight += w;
+    }
+
+    return sum / weight;
+}
+
+// params: (threshold, knee, _, _)
+@fragment
+fn fs_threshold(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let c = sample(fin.uv);
+    let l = max(c.r, max(c.g, c.b));
+    let knee = max(push.params.y, 0.0001);
+
+    var soft = clamp(l - push.params.x + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+
+    return c * max(soft, l - push.params.x) / max(l, 0.0001);
+}
+
+@fragment
+fn fs_downsample(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let o = push.texel;
+    return (sample(fin.uv + vec2<f32>(-o.x, -o.y))
+        + sample(fin.uv + vec2<f32>(o.x, -o.y))
+        + sample(fin.uv + vec2<f32>(-o.x, o.y))
+        + sample(fin.uv + vec2<f32>(o.x, o.y))) * 0.25;
+}
+
+// 3x3 tent, blended additively into the larger mip
+@fragment
+fn fs_upsample(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let o = push.texel;
+    return (sample(fin.uv + vec2<f32>(-o.x, -o.y))
+        + sample(fin.uv + vec2<f32>(0.0, -o.y)) * 2.0
+        + sample(fin.uv + vec2<f32>(o.x, -o.y))
+        + sample(fin.uv + vec2<f32>(-o.x, 0.0)) * 2.0
+        + sample(fin.uv) * 4.0
+        + sample(fin.uv + vec2<f32>(o.x, 0.0)) * 2.0
+        + sample(fin.uv + vec2<f32>(-o.x, o.y))
+        + sample(fin.uv + vec2<f32>(0.0, o.y)) * 2.0
+        + sample(fin.uv + vec2<f32>(o.x, o.y))) / 16.0;
+}
+
+// params: (intensity, _, _, _)
+@fragment
+fn fs_composite(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let bloom = textureSampleLevel(bloom_t, source_s, fin.uv, 0.0);
+    return sample(fin.uv) + bloom * push.params.x;
+}
+
+// params: (strength, radius, softness, _)
+@fragment
+fn fs_vignette(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let d = length(fin.uv - 0.5) * 1.41421356;
+    let fade = smoothstep(push.params.y, push.params.y + push.params.z, d);
+    return sample(fin.uv) * (1.0 - push.params.x * fade);
+}
+
+// params: (offset, _, _, _)
+@fragment
+fn fs_chromatic(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let o = (fin.uv - 0.5) * push.params.x;
+    let r = sample(fin.uv + o);
+    let g = sample(fin.uv);
+    let b = sample(fin.uv - o);
+    return vec4<f32>(r.r, g.g, b.b, max(max(r.a, g.a), b.a));
+}
+
+fn hash(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3<f32>(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// params: (strength, time, _, _)
+@fragment
+fn fs_grain(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let c = sample(fin.uv);
+    let n = hash(vec3<f32>(fin.pos.xy, fract(push.params.y) * 100.0)) - 0.5;
+    return vec4<f32>(c.rgb + n * push.params.x * c.a, c.a);
+}
+
+fn srgb_encode(linear: vec3<f32>) -> vec3<f32> {
+    let c = clamp(linear, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, c <= vec3<f32>(0.0031308));
+}
+
+// params: (alpha mode, srgb encode, _, _)
+// alpha mode: 0 = opaque, 1 = premultiplied, 2 = postmultiplied
+@fragment
+fn fs_output(fin: FragmentInput) -> @location(0) vec4<f32> {
+    // the scene is premultiplied
+    let c = sample(fin.uv);
+    let a = clamp(c.a, 0.0, 1.0);
+    let mode = u32(push.params.x);
+
+    // composited over black
+    var rgb = c.rgb;
+    if mode != 0u {
+        rgb = c.rgb / max(a, 0.0001);
+    }
+
+    // the transfer function applies to the unpremultiplied color
+    if push.params.y != 0.0 {
+        rgb = srgb_encode(rgb);
+    }
+
+    switch mode {
+        case 0u: {
+            return vec4<f32>(rgb, 1.0);
+        }
+        case 2u: {
+            return vec4<f32>(rgb, a);
+        }
+        default: {
+            return vec4<f32>(rgb * a, a);
+        }
+    }
+}
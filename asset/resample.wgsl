@@ -0,0 +1,59 @@
+struct VertexInput {
+    @builtin(vertex_index) vi: u32,
+};
+
+struct FragmentInput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+struct Push {
+    // destination uv to source uv
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+};
+
+@group(0)
+@binding(0)
+var source: texture_2d<f32>;
+
+// push constants, or a uniform buffer without them
+//!ifdef PARAMS_UNIFORM
+@group(1)
+@binding(0)
+var<uniform> push: Push;
+//!else
+var<push_constant> push: Push;
+//!endif
+
+@vertex
+fn vs_main(vin: VertexInput) -> FragmentInput {
+    let p = vec2<f32>(f32(vin.vi % 2u), f32(vin.vi / 2u)) * 2.0 - 1.0;
+
+    var fin: FragmentInput;
+    fin.uv = vec2<f32>(p.x * 0.5 + 0.5, 0.5 - p.y * 0.5);
+    fin.pos = vec4<f32>(p, 0.0, 1.0);
+    return fin;
+}
+
+fn load(p: vec2<i32>) -> f32 {
+    let dim = vec2<i32>(textureDimensions(source));
+    return textureLoad(source, clamp(p, vec2<i32>(0), dim - 1), 0).r;
+}
+
+// manual bilinear filtering, r32float is not filterable everywhere
+@fragment
+fn fs_main(fin: FragmentInput) -> @location(0) vec4<f32> {
+    let uv = fin.uv * push.scale + push.offset;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+        return vec4<f32>(0.0);
+    }
+
+    let p = uv * vec2<f32>(textureDimensions(source)) - 0.5;
+    let i = vec2<i32>(floor(p));
+    let f = fract(p);
+
+    let top = mix(load(i), load(i + vec2<i32>(1, 0)), f.x);
+    let bottom = mix(load(i + vec2<i32>(0, 1)), load(i + vec2<i32>(1, 1)), f.x);
+    return vec4<f32>(mix(top, bottom, f.y), 0.0, 0.0, 0.0);
+}
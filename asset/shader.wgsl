@@ -65,24 +65,99 @@ fn fs_main(fin: FragmentInput) -> @location(0) vec4<f32> {
     return vec4<f32>(1.0, 1.0, 1.0, t * 0.001);
 } */
 
+// overridden by the renderer to fit the adapter limits
+const UPDATE_WORKGROUP_SIZE: u32 = 256u;
+const SHADOW_WORKGROUP_SIZE: u32 = 16u;
+
+// specialized by the renderer with defines from the runtime toggles:
+// FAST_FADE, SUBTRACT_FADE, CLEAR, HEAVY_POINTS, NO_CURSOR, HEAVY_CURSOR,
+// NO_NOISE, HEAVY_NOISE and FREEZE_NOISE
+
+//!ifdef FAST_FADE
+const SHADOW_SUB: f32 = 0.005;
+const SHADOW_MUL: f32 = 0.98;
+//!else
+const SHADOW_SUB: f32 = 0.00015;
+const SHADOW_MUL: f32 = 0.999;
+//!endif
+
+//!ifdef HEAVY_POINTS
+const POINT: f32 = 0.02;
+//!else
+const POINT: f32 = 0.002;
+//!endif
+
+//!ifdef HEAVY_CURSOR
+const CURSOR_STRENGTH: f32 = 0.00001;
+//!else
+const CURSOR_STRENGTH: f32 = 0.000001;
+//!endif
+
+//!ifdef HEAVY_NOISE
+const NOISE_STRENGTH: f32 = 0.0001;
+//!else
+const NOISE_STRENGTH: f32 = 0.00001;
+//!endif
+
 struct UpdatePush {
     cursor: vec2<f32>,
     time: f32,
-    flags: u32,
+    _pad: u32,
 };
 
+// push constants, or a uniform buffer without them
+//!ifdef PARAMS_UNIFORM
+@group(1)
+@binding(0)
+var<uniform> update_push: UpdatePush;
+//!else
 var<push_constant> update_push: UpdatePush;
-
-struct ShadowPush {
-    flags: u32,
+//!endif
+
+//!ifdef TARGET_BUFFER
+// fallback without read-write storage textures,
+// copied into the texture the render passes read
+struct Accum {
+    size: vec2<u32>,
+    // row length in texels, padded for the copy
+    stride: u32,
+    _pad: u32,
+    texels: array<f32>,
 };
 
-var<push_constant> shadow_push: ShadowPush;
+@group(0)
+@binding(0)
+var<storage, read_write> accum: Accum;
+
+fn accum_size() -> vec2<u32> {
+    return accum.size;
+}
+
+fn accum_load(coords: vec2<u32>) -> f32 {
+    return accum.texels[coords.y * accum.stride + coords.x];
+}
 
+fn accum_store(coords: vec2<u32>, value: f32) {
+    accum.texels[coords.y * accum.stride + coords.x] = value;
+}
+//!else
 @group(0)
 @binding(0)
 var texture: texture_storage_2d<r32float, read_write>;
 
+fn accum_size() -> vec2<u32> {
+    return textureDimensions(texture);
+}
+
+fn accum_load(coords: vec2<u32>) -> f32 {
+    return textureLoad(texture, coords).x;
+}
+
+fn accum_store(coords: vec2<u32>, value: f32) {
+    textureStore(texture, coords, vec4<f32>(value));
+}
+//!endif
+
 @group(0)
 @binding(1)
 var<storage, read_write> points: array<vec4<f32>>;
@@ -92,35 +167,35 @@ var<storage, read_write> points: array<vec4<f32>>;
 // var s_texture: texture_storage_2d<r32float, read_write>;
 
 @compute
-@workgroup_size(16, 16, 1)
+@workgroup_size(SHADOW_WORKGROUP_SIZE, SHADOW_WORKGROUP_SIZE, 1)
 fn cs_main_shadow(@builtin(global_invocation_id) id: vec3<u32>) {
-    var shadow_sub = 0.00015;
-    var shadow_mul = 0.999;
-    if (shadow_push.flags & 1u) != 0u {
-        shadow_sub = 0.005;
-        shadow_mul = 0.98;
+    if any(id.xy >= accum_size()) {
+        return;
     }
 
     let coords = id.xy;
-    var pix = textureLoad(texture, coords);
-    if (shadow_push.flags & 2u) != 0u {
-        pix -= shadow_sub;
-    } else {
-        pix *= shadow_mul;
-    }
-
-    if (shadow_push.flags & 2048u) != 0u {
-        pix = vec4<f32>(0.0);
-    }
-
-    textureStore(texture, coords, pix);
+    //!ifdef CLEAR
+    let pix = 0.0;
+    //!else
+    var pix = accum_load(coords);
+    //!ifdef SUBTRACT_FADE
+    pix -= SHADOW_SUB;
+    //!else
+    pix *= SHADOW_MUL;
+    //!endif
+    //!endif
+
+    accum_store(coords, pix);
 }
 
 @compute
-@workgroup_size(512, 1, 1)
-fn cs_main_update(@builtin(global_invocation_id) id: vec3<u32>) {
-    // let i = id.x + id.y * 8u + id.z * 64u;
-    let i = id.x;
+@workgroup_size(UPDATE_WORKGROUP_SIZE, 1, 1)
+fn cs_main_update(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    // large dispatches are split into rows
+    let i = id.x + id.y * groups.x * UPDATE_WORKGROUP_SIZE;
 
     if i >= arrayLength(&points) {
         return;
@@ -129,43 +204,35 @@ fn cs_main_update(@builtin(global_invocation_id) id: vec3<u32>) {
     let now = points[i];
     var pos = now.xy;
 
-    var speed = 0.005;
-    if (update_push.flags & 128u) != 0u {
-        speed = 0.0;
-    }
-    let time = speed * update_push.time;
-    // let time = 10.0 * update_push.time;
     var vel = now.zw * 0.998; // 0.9985
+
+    //!ifndef NO_NOISE
+    //!ifdef FREEZE_NOISE
+    let time = 0.0;
+    //!else
+    let time = 0.005 * update_push.time;
+    //!endif
+    // let time = 10.0 * update_push.time;
     /* let noise_dir = vec2<f32>(
         simplex_noise_3d(vec3<f32>(pos, time - 1000.0)),
         simplex_noise_3d(vec3<f32>(pos, time + 1000.0)),
     ); */
     let noise_dir_a = simplex_noise_3d(vec3<f32>(pos, time)) * 3.14159265358979323846;
     let noise_dir = vec2<f32>(cos(noise_dir_a), sin(noise_dir_a)) * 0.2;
-    var noise_strength = 0.00001;
-    if (update_push.flags & 32u) != 0u {
-        noise_strength *= 0.0;
-    }
-    if (update_push.flags & 64u) != 0u {
-        noise_strength *= 10.0;
-    }
-    vel += noise_dir * noise_strength;
+    vel += noise_dir * NOISE_STRENGTH;
+    //!endif
 
-    let cursor_flipped = update_push.cursor / vec2<f32>(textureDimensions(texture)) * 2.0 - 1.0;
+    //!ifndef NO_CURSOR
+    // the cursor is normalized to 0..1, independent of the render scale
+    let cursor_flipped = update_push.cursor * 2.0 - 1.0;
     let cursor = vec2<f32>(cursor_flipped.x, -cursor_flipped.y);
     let cursor_dir = cursor - pos;
 
     let angle = atan2(cursor_dir.y, cursor_dir.x);
     let dist_sqr = (cursor_dir.x * cursor_dir.x + cursor_dir.y * cursor_dir.y);
     // let vel = normalize(dir) / length(dir) * 0.001;
-    var cursor_strength = 0.000001;
-    if (update_push.flags & 8u) != 0u {
-        cursor_strength *= 0.0;
-    }
-    if (update_push.flags & 16u) != 0u {
-        cursor_strength *= 10.0;
-    }
-    vel += vec2<f32>(sin(angle), -cos(angle)) / dist_sqr * cursor_strength * 0.01;
+    vel += vec2<f32>(sin(angle), -cos(angle)) / dist_sqr * CURSOR_STRENGTH * 0.01;
+    //!endif
 
     pos += vel;
 
@@ -173,15 +240,12 @@ fn cs_main_update(@builtin(global_invocation_id) id: vec3<u32>) {
 
     points[i] = vec4<f32>(pos, vel);
 
-    let coords = vec2<u32>((pos + 1.0) * 0.5 * vec2<f32>(textureDimensions(texture)));
-
-    var point = 0.002;
-    if (update_push.flags & 4u) != 0u {
-        point = 0.02;
-    }
+    let dim = accum_size();
+    let coords = min(vec2<u32>((pos + 1.0) * 0.5 * vec2<f32>(dim)), dim - 1u);
 
     // textureStore(texture, coords, vec4<f32>(1.0));// min(textureLoad(texture, coords) + 0.5, vec4<f32>(1.0)));
     // textureStore(texture, vec2<u32>(10u, 10u), vec4<f32>(1.0));
 
-    textureStore(texture, coords, min(textureLoad(texture, coords) + point, vec4<f32>(1.0)));
+    // unclamped, the blit pass maps the range with the exposure
+    accum_store(coords, accum_load(coords) + POINT);
 }
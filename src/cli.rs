@@ -0,0 +1,83 @@
+use std::{env, path::PathBuf, process};
+
+use anyhow::{anyhow, bail, Result};
+
+//
+
+/// command line arguments, these override the settings file
+#[derive(Debug, Default)]
+pub struct Args {
+    pub shader_dir: Option<PathBuf>,
+    /// adapter index or part of its name
+    pub adapter: Option<String>,
+    pub list_adapters: bool,
+    pub screensaver: bool,
+    /// XScreenSaver window to draw into, implies `screensaver`
+    pub window_id: Option<u32>,
+    /// snapshot to continue from
+    pub load: Option<PathBuf>,
+}
+
+//
+
+const USAGE: &str = "\
+usage: flowfield [options]
+
+options:
+  --shader-dir <path>      load the shaders from <path> instead of the built-in ones
+  --adapter <index|name>   use the GPU at <index> or with <name> in its name
+  --list-adapters          print the available GPUs and exit
+  --screensaver, -root     fullscreen, exit on input and rotate random presets
+  -window-id <id>          draw into the X11 window <id>, for XScreenSaver
+  --load <path>            continue from a snapshot saved with the S key
+  -h, --help               print this help";
+
+//
+
+impl Args {
+    /// parse `env::args`, prints the usage and exits on errors
+    pub fn parse() -> Self {
+        match Self::try_parse(env::args().skip(1)) {
+            Ok(args) => args,
+            Err(err) => {
+                eprintln!("{err}\n\n{USAGE}");
+                process::exit(2);
+            }
+        }
+    }
+
+    pub fn try_parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut out = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for {arg}"))
+            };
+
+            match arg.as_str() {
+                "--shader-dir" => out.shader_dir = Some(value()?.into()),
+                "--adapter" => out.adapter = Some(value()?),
+                "--list-adapters" => out.list_adapters = true,
+                "--load" => out.load = Some(value()?.into()),
+                "--screensaver" | "-root" => out.screensaver = true,
+                "-window-id" | "--window-id" => {
+                    let id = value()?;
+                    let parsed = match id.strip_prefix("0x") {
+                        Some(hex) => u32::from_str_radix(hex, 16),
+                        None => id.parse(),
+                    };
+                    out.window_id = Some(parsed.map_err(|_| anyhow!("invalid window id {id}"))?);
+                    out.screensaver = true;
+                }
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    process::exit(0);
+                }
+                _ => bail!("unknown argument {arg}"),
+            }
+        }
+
+        Ok(out)
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+//
+
+/// what happens to the accumulated image when the window is resized
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResizeMode {
+    /// start over from an empty image
+    Clear,
+    /// stretch the image to the new size
+    #[default]
+    Stretch,
+    /// keep the aspect ratio and fill the new size, cropping the edges
+    Crop,
+    /// keep the aspect ratio and fit the whole image, adding empty borders
+    Letterbox,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExposureSettings {
+    pub auto: bool,
+    pub percentile: f32,
+    pub adaptation_speed: f32,
+    pub manual: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostSettings {
+    /// the order of the post passes, disabled passes are skipped
+    pub order: Vec<PostPass>,
+    pub blur: BlurSettings,
+    pub bloom: BloomSettings,
+    pub vignette: VignetteSettings,
+    pub chromatic: ChromaticSettings,
+    pub grain: GrainSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PostPass {
+    Blur,
+    Bloom,
+    Vignette,
+    Chromatic,
+    Grain,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlurSettings {
+    pub enabled: bool,
+    pub sigma: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub knee: f32,
+    pub intensity: f32,
+    pub levels: u32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VignetteSettings {
+    pub enabled: bool,
+    pub strength: f32,
+    pub radius: f32,
+    pub softness: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChromaticSettings {
+    pub enabled: bool,
+    pub offset: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrainSettings {
+    pub enabled: bool,
+    pub strength: f32,
+}
+
+//
+
+impl Default for ExposureSettings {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            percentile: 0.99,
+            adaptation_speed: 2.0,
+            manual: 1.0,
+        }
+    }
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            order: vec![
+                PostPass::Blur,
+                PostPass::Bloom,
+                PostPass::Chromatic,
+                PostPass::Vignette,
+                PostPass::Grain,
+            ],
+            blur: <_>::default(),
+            bloom: <_>::default(),
+            vignette: <_>::default(),
+            chromatic: <_>::default(),
+            grain: <_>::default(),
+        }
+    }
+}
+
+impl Default for BlurSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sigma: 1.0,
+        }
+    }
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.6,
+            knee: 0.2,
+            intensity: 0.5,
+            levels: 6,
+        }
+    }
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strength: 0.5,
+            radius: 0.6,
+            softness: 0.5,
+        }
+    }
+}
+
+impl Default for ChromaticSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            offset: 0.005,
+        }
+    }
+}
+
+impl Default for GrainSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strength: 0.05,
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use wgpu::{
+    Adapter, AdapterInfo, Backends, Instance, InstanceDescriptor, RequestAdapterOptions, Surface,
+};
+use winit::window::Window;
+
+use crate::settings::{AdapterSettings, GraphicsBackends, GraphicsSettings};
+
+use super::surface::SurfaceBuilder;
+
+//
+
+/// try the backend sets from [`backend_attempts`] in order,
+/// until one of them has an adapter that can draw to the window
+pub async fn request(
+    s: &GraphicsSettings,
+    window: Arc<Window>,
+) -> Result<(Arc<Instance>, SurfaceBuilder, Adapter)> {
+    let attempts = backend_attempts(&s.allowed_backends);
+
+    for (n, backends) in attempts.iter().copied().enumerate() {
+        // the indices are the ones `--list-adapters` prints for the first backend set,
+        // another set numbers the adapters differently
+        let mut filter = s.adapter.clone();
+        if n > 0 && filter.index.take().is_some() {
+            tracing::warn!(
+                "adapter.index refers to the adapters of {:?}, ignoring it with {backends:?}",
+                attempts[0]
+            );
+        }
+
+        let instance = Arc::new(Instance::new(InstanceDescriptor {
+            backends,
+            ..<_>::default()
+        }));
+
+        let surface_builder = match SurfaceBuilder::new(instance.clone(), window.clone()) {
+            Ok(surface_builder) => surface_builder,
+            Err(err) => {
+                tracing::warn!("Failed to create a surface with {backends:?}: {err}");
+                continue;
+            }
+        };
+
+        let gpu = match select(&instance, backends, s, &filter, &surface_builder.surface) {
+            Some(gpu) => Some(gpu),
+            None => {
+                instance
+                    .request_adapter(&RequestAdapterOptions {
+                        power_preference: s.gpu_preference.to_power_preference(),
+                        force_fallback_adapter: s.force_software_rendering,
+                        compatible_surface: Some(&surface_builder.surface),
+                    })
+                    .await
+            }
+        };
+
+        if let Some(gpu) = gpu {
+            let info = gpu.get_info();
+            tracing::info!("using {} with {:?}", info.name, info.backend);
+            return Ok((instance, surface_builder, gpu));
+        }
+
+        tracing::warn!("no suitable GPU with {backends:?}");
+    }
+
+    bail!("Could not find a suitable GPU, tried the backends {attempts:?}")
+}
+
+/// the backend sets to try, in order:
+/// 1. the configured backends
+/// 2. every tier 1 backend
+/// 3. GL
+///
+/// backends that are not available on this OS are skipped,
+/// with a warning if they are not enabled by default
+pub fn backend_attempts(config: &GraphicsBackends) -> Vec<Backends> {
+    if config.dx11 {
+        tracing::info!("wgpu has no DX11 backend, dx11 uses DX12 which runs on DX11 class GPUs");
+    }
+
+    let available = available_backends();
+    let configured = config.to_backends();
+    let defaults = GraphicsBackends::default().to_backends();
+    for (name, backend) in (configured - available).iter_names() {
+        if defaults.contains(backend) {
+            tracing::debug!("the {name} backend is not available on this OS, ignoring it");
+        } else {
+            tracing::warn!("the {name} backend is not available on this OS, ignoring it");
+        }
+    }
+    if (configured & available).is_empty() {
+        tracing::warn!("none of the allowed backends are available, using the fallbacks");
+    }
+
+    let mut attempts = vec![];
+    for backends in [configured, Backends::PRIMARY, Backends::GL] {
+        let backends = backends & available;
+        if !backends.is_empty() && !attempts.contains(&backends) {
+            attempts.push(backends);
+        }
+    }
+    attempts
+}
+
+/// backends compiled into wgpu for this OS
+pub fn available_backends() -> Backends {
+    let desktop = cfg!(any(
+        windows,
+        all(unix, not(target_os = "ios"), not(target_os = "macos"))
+    ));
+
+    let mut backends = Backends::empty();
+    backends.set(Backends::VULKAN, desktop);
+    backends.set(Backends::GL, desktop);
+    backends.set(
+        Backends::METAL,
+        cfg!(any(target_os = "macos", target_os = "ios")),
+    );
+    backends.set(Backends::DX12, cfg!(windows));
+    backends.set(Backends::BROWSER_WEBGPU, cfg!(target_arch = "wasm32"));
+    backends
+}
+
+/// every adapter of `backends`, in the order the indices refer to
+pub fn enumerate(instance: &Instance, backends: Backends) -> Vec<Adapter> {
+    instance.enumerate_adapters(backends)
+}
+
+/// print the adapters of the configured backends for `--list-adapters`
+pub fn print_list(s: &GraphicsSettings) {
+    let Some(backends) = backend_attempts(&s.allowed_backends).first().copied() else {
+        println!("no backends available");
+        return;
+    };
+
+    let instance = Instance::new(InstanceDescriptor {
+        backends,
+        ..<_>::default()
+    });
+
+    let adapters = enumerate(&instance, backends);
+    if adapters.is_empty() {
+        println!("no adapters found with {backends:?}");
+    }
+    for (i, adapter) in adapters.iter().enumerate() {
+        println!("{}", describe(i, &adapter.get_info()));
+    }
+}
+
+/// index, name, vendor, device type, backend and driver
+pub fn describe(index: usize, info: &AdapterInfo) -> String {
+    let driver = match (info.driver.is_empty(), info.driver_info.is_empty()) {
+        (true, _) => "unknown driver".to_string(),
+        (false, true) => info.driver.clone(),
+        (false, false) => format!("{} {}", info.driver, info.driver_info),
+    };
+
+    format!(
+        "{index}: {} ({}, {:?}, {:?}, {driver})",
+        info.name,
+        vendor_name(info.vendor),
+        info.device_type,
+        info.backend,
+    )
+}
+
+/// the adapter matching every set field of `filter`,
+/// `None` falls back to `gpu_preference`
+pub fn select(
+    instance: &Instance,
+    backends: Backends,
+    s: &GraphicsSettings,
+    filter: &AdapterSettings,
+    surface: &Surface,
+) -> Option<Adapter> {
+    let adapters = enumerate(instance, backends);
+    for (i, adapter) in adapters.iter().enumerate() {
+        tracing::info!("adapter {}", describe(i, &adapter.get_info()));
+    }
+
+    if filter.index.is_none() && filter.name.is_none() && filter.kind.is_none() {
+        return None;
+    }
+
+    let selected = adapters
+        .into_iter()
+        .enumerate()
+        .filter(|(i, adapter)| matches(filter, *i, &adapter.get_info()))
+        .find(|(i, adapter)| {
+            let supported = adapter.is_surface_supported(surface);
+            if !supported {
+                tracing::warn!("adapter {i} can't draw to the window, skipping it");
+            }
+            supported
+        });
+
+    match selected {
+        Some((i, adapter)) => {
+            tracing::info!("selected adapter {i}");
+            Some(adapter)
+        }
+        None => {
+            tracing::warn!(
+                "no adapter matches {filter:?}, falling back to {:?}",
+                s.gpu_preference
+            );
+            None
+        }
+    }
+}
+
+fn matches(filter: &AdapterSettings, index: usize, info: &AdapterInfo) -> bool {
+    filter.index.is_none_or(|i| i == index)
+        && filter
+            .name
+            .as_ref()
+            .is_none_or(|name| info.name.to_lowercase().contains(&name.to_lowercase()))
+        && filter
+            .kind
+            .is_none_or(|kind| kind.to_device_type() == info.device_type)
+}
+
+fn vendor_name(vendor: u32) -> String {
+    match vendor {
+        0x1002 => "AMD".to_string(),
+        0x10de => "NVIDIA".to_string(),
+        0x8086 => "Intel".to_string(),
+        0x106b => "Apple".to_string(),
+        0x13b5 => "ARM".to_string(),
+        0x5143 => "Qualcomm".to_string(),
+        0x10005 => "Mesa".to_string(),
+        0 => "unknown vendor".to_string(),
+        other => format!("vendor {other:#06x}"),
+    }
+}
@@ -0,0 +1,82 @@
+use wgpu::Limits;
+
+//
+
+/// compute workgroup sizes picked to fit the adapter limits
+#[derive(Debug, Clone, Copy)]
+pub struct WorkgroupSizes {
+    /// invocations per 1D workgroup of the particle update pass
+    pub update: u32,
+    /// side length of the square 2D workgroups of the shadow pass
+    pub shadow: u32,
+}
+
+//
+
+impl WorkgroupSizes {
+    const MAX_UPDATE: u32 = 512;
+    const MAX_SHADOW: u32 = 16;
+
+    pub fn new(limits: &Limits) -> Self {
+        let invocations = limits.max_compute_invocations_per_workgroup.max(1);
+
+        let update = prev_power_of_two(
+            Self::MAX_UPDATE
+                .min(invocations)
+                .min(limits.max_compute_workgroup_size_x.max(1)),
+        );
+
+        let mut shadow = prev_power_of_two(
+            Self::MAX_SHADOW
+                .min(limits.max_compute_workgroup_size_x.max(1))
+                .min(limits.max_compute_workgroup_size_y.max(1)),
+        );
+        while shadow * shadow > invocations {
+            shadow /= 2;
+        }
+
+        Self { update, shadow }
+    }
+
+    /// replace the `const` declarations in `shader.wgsl` with the picked sizes
+    pub fn apply(&self, src: &mut String) {
+        override_const(src, "UPDATE_WORKGROUP_SIZE", self.update);
+        override_const(src, "SHADOW_WORKGROUP_SIZE", self.shadow);
+    }
+
+    /// workgroup count for `len` particles,
+    /// split into rows if a single row would exceed the limit
+    pub fn update_dispatch(&self, limits: &Limits, len: u32) -> (u32, u32) {
+        let groups = len.div_ceil(self.update);
+        let max = limits.max_compute_workgroups_per_dimension.max(1);
+        if groups <= max {
+            (groups, 1)
+        } else {
+            (max, groups.div_ceil(max))
+        }
+    }
+
+    /// workgroup count covering every texel of a `width`x`height` texture
+    pub fn shadow_dispatch(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        (width.div_ceil(self.shadow), height.div_ceil(self.shadow))
+    }
+}
+
+/// replace the value of a `const NAME: u32 = ...;` declaration
+pub fn override_const(src: &mut String, name: &str, value: u32) {
+    let pattern = format!("const {name}: u32 = ");
+    let Some(start) = src.find(&pattern) else {
+        tracing::warn!("shader constant {name} not found");
+        return;
+    };
+    let value_start = start + pattern.len();
+    let Some(len) = src[value_start..].find(';') else {
+        return;
+    };
+
+    src.replace_range(value_start..value_start + len, &format!("{value}u"));
+}
+
+fn prev_power_of_two(v: u32) -> u32 {
+    1 << (31 - v.max(1).leading_zeros())
+}
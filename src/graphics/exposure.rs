@@ -0,0 +1,263 @@
+use std::mem::size_of;
+
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use wgpu::*;
+
+use crate::config::ExposureSettings;
+
+use super::{
+    params::{ParamMode, Params},
+    preprocess::Preprocessor,
+    shaders::{load_module, validated},
+};
+
+//
+
+/// exposure statistics of the accumulation texture
+///
+/// a histogram pass followed by a single workgroup
+/// resolve pass, the result stays on the GPU
+pub struct Exposure {
+    settings: ExposureSettings,
+
+    histogram: Buffer,
+    stats: Buffer,
+    uniform: Buffer,
+
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    params: Params<ExposurePush>,
+    layout: PipelineLayout,
+    histogram_pipeline: ComputePipeline,
+    resolve_pipeline: ComputePipeline,
+
+    size: (u32, u32),
+}
+
+/// matches `Stats` in `exposure.wgsl` and `Exposure` in `blit.wgsl`
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct ExposureStats {
+    pub max: f32,
+    pub mean: f32,
+    pub percentile: f32,
+    pub exposure: f32,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub(super) struct ExposurePush {
+    pub dt: f32,
+    pub speed: f32,
+    pub percentile: f32,
+    pub manual: f32,
+    pub flags: u32,
+}
+
+const BINS: u64 = 256;
+
+//
+
+impl Exposure {
+    pub fn new(
+        device: &Device,
+        shaders: &Preprocessor,
+        param_mode: ParamMode,
+        settings: ExposureSettings,
+        target_view: &TextureView,
+        size: (u32, u32),
+    ) -> Result<Self> {
+        let histogram = device.create_buffer(&BufferDescriptor {
+            label: Some("exposure histogram"),
+            size: (BINS + 1) * size_of::<u32>() as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let stats = device.create_buffer(&BufferDescriptor {
+            label: Some("exposure stats"),
+            size: size_of::<ExposureStats>() as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let uniform = device.create_buffer(&BufferDescriptor {
+            label: Some("exposure uniform"),
+            size: size_of::<ExposureStats>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let storage = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage(1),
+                storage(2),
+            ],
+        });
+
+        // set once for each of the two dispatches
+        let params = Params::new(device, param_mode, ShaderStages::COMPUTE, 1, 2);
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &params.bind_group_layouts(&[&bind_group_layout]),
+            push_constant_ranges: params.push_constant_ranges(),
+        });
+
+        let (histogram_pipeline, resolve_pipeline) =
+            Self::create_pipelines(device, shaders, &layout)?;
+
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, target_view, &histogram, &stats);
+
+        Ok(Self {
+            settings,
+
+            histogram,
+            stats,
+            uniform,
+
+            bind_group_layout,
+            bind_group,
+            params,
+            layout,
+            histogram_pipeline,
+            resolve_pipeline,
+
+            size,
+        })
+    }
+
+    fn create_pipelines(
+        device: &Device,
+        shaders: &Preprocessor,
+        layout: &PipelineLayout,
+    ) -> Result<(ComputePipeline, ComputePipeline)> {
+        validated(device, || {
+            let module = load_module(device, shaders, "exposure.wgsl")?;
+
+            let histogram_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("exposure histogram pipeline"),
+                layout: Some(layout),
+                module: &module,
+                entry_point: "cs_main_histogram",
+            });
+
+            let resolve_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("exposure resolve pipeline"),
+                layout: Some(layout),
+                module: &module,
+                entry_point: "cs_main_resolve",
+            });
+
+            Ok((histogram_pipeline, resolve_pipeline))
+        })
+    }
+
+    /// recompile the shader, keeping the old pipelines on failure
+    pub fn reload(&mut self, device: &Device, shaders: &Preprocessor) -> Result<()> {
+        (self.histogram_pipeline, self.resolve_pipeline) =
+            Self::create_pipelines(device, shaders, &self.layout)?;
+        Ok(())
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        target_view: &TextureView,
+        histogram: &Buffer,
+        stats: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("exposure bind group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(target_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: histogram.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: stats.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// the resolved [`ExposureStats`] as a uniform buffer
+    pub fn uniform(&self) -> &Buffer {
+        &self.uniform
+    }
+
+    pub fn resized(&mut self, device: &Device, target_view: &TextureView, size: (u32, u32)) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            target_view,
+            &self.histogram,
+            &self.stats,
+        );
+        self.size = size;
+    }
+
+    pub fn encode(&self, queue: &Queue, encoder: &mut CommandEncoder, dt: f32) {
+        let push = ExposurePush {
+            dt,
+            speed: self.settings.adaptation_speed,
+            percentile: self.settings.percentile.clamp(0.0, 1.0),
+            manual: self.settings.manual,
+            flags: self.settings.auto as u32,
+        };
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("exposure pass"),
+            timestamp_writes: None,
+        });
+
+        pass.set_pipeline(&self.histogram_pipeline);
+        self.params.set(queue, &mut pass, &push);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups(self.size.0.div_ceil(16), self.size.1.div_ceil(16), 1);
+
+        pass.set_pipeline(&self.resolve_pipeline);
+        self.params.set(queue, &mut pass, &push);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups(1, 1, 1);
+
+        drop(pass);
+
+        encoder.copy_buffer_to_buffer(
+            &self.stats,
+            0,
+            &self.uniform,
+            0,
+            size_of::<ExposureStats>() as u64,
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::{bail, Result};
+use wgpu::{Adapter, DownlevelFlags, Features, Limits};
+
+use crate::settings::GraphicsSettings;
+
+//
+
+/// the features the renderer can't run without,
+/// every feature it uses has a fallback
+pub const REQUIRED_FEATURES: Features = Features::empty();
+
+/// the simulation runs in compute shaders
+pub const REQUIRED_DOWNLEVEL: DownlevelFlags = DownlevelFlags::COMPUTE_SHADERS;
+
+/// features that switch to a better code path when the adapter has them
+pub const OPTIONAL_FEATURES: &[(Features, &str)] = &[
+    (
+        Features::PUSH_CONSTANTS,
+        "push constants instead of a uniform buffer",
+    ),
+    (
+        Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+        "read-write storage textures instead of a storage buffer",
+    ),
+    (
+        Features::FLOAT32_FILTERABLE,
+        "linear filtering of the accumulation texture",
+    ),
+    (Features::TIMESTAMP_QUERY, "GPU pass timings"),
+];
+
+/// the features and limits requested from the device
+#[derive(Debug, Clone)]
+pub struct DeviceRequest {
+    pub features: Features,
+    pub limits: Limits,
+}
+
+//
+
+impl DeviceRequest {
+    /// check the adapter against the requirements
+    /// and pick the optional features it has
+    pub fn negotiate(adapter: &Adapter, s: &GraphicsSettings) -> Result<Self> {
+        let name = adapter.get_info().name;
+
+        let missing = REQUIRED_FEATURES - adapter.features();
+        if !missing.is_empty() {
+            bail!("{name} is missing the required features {missing:?}");
+        }
+
+        let missing = REQUIRED_DOWNLEVEL - adapter.get_downlevel_capabilities().flags;
+        if !missing.is_empty() {
+            bail!("{name} is missing the required capabilities {missing:?}");
+        }
+
+        let adapter_limits = adapter.limits();
+        let mut missing = vec![];
+        Self::required_limits().check_limits_with_fail_fn(
+            &adapter_limits,
+            false,
+            |limit, needed, has| missing.push(format!("{limit} (needs {needed}, has {has})")),
+        );
+        if !missing.is_empty() {
+            bail!(
+                "{name} does not meet the required limits: {}",
+                missing.join(", ")
+            );
+        }
+
+        // only ask for what is used
+        let mut wanted = Features::all();
+        wanted.set(Features::PUSH_CONSTANTS, s.push_constants);
+        wanted.set(
+            Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            s.storage_textures,
+        );
+        wanted.set(Features::TIMESTAMP_QUERY, s.gpu_timings);
+
+        let mut features = REQUIRED_FEATURES;
+        for (feature, path) in OPTIONAL_FEATURES {
+            if !wanted.contains(*feature) {
+                tracing::debug!("{feature:?} not requested, no {path}");
+            } else if adapter.features().contains(*feature) {
+                tracing::debug!("{feature:?} enabled, using {path}");
+                features |= *feature;
+            } else {
+                tracing::info!("{feature:?} is not supported, no {path}");
+            }
+        }
+
+        Ok(Self {
+            features,
+            limits: Self::optional_limits(&adapter_limits, features),
+        })
+    }
+
+    /// the limits every code path works with
+    pub fn required_limits() -> Limits {
+        Limits::downlevel_defaults()
+    }
+
+    /// the required limits, raised to what the adapter has
+    /// where a higher limit allows more
+    fn optional_limits(adapter: &Limits, features: Features) -> Limits {
+        let required = Self::required_limits();
+        Limits {
+            // larger windows and render scales
+            max_texture_dimension_1d: adapter.max_texture_dimension_1d,
+            max_texture_dimension_2d: adapter.max_texture_dimension_2d,
+            // the storage buffer fallback holds the whole image
+            max_storage_buffer_binding_size: adapter.max_storage_buffer_binding_size,
+            max_buffer_size: adapter.max_buffer_size,
+            // larger workgroups and dispatches, see `WorkgroupSizes`
+            max_compute_workgroup_size_x: adapter.max_compute_workgroup_size_x,
+            max_compute_workgroup_size_y: adapter.max_compute_workgroup_size_y,
+            max_compute_invocations_per_workgroup: adapter.max_compute_invocations_per_workgroup,
+            max_compute_workgroups_per_dimension: adapter.max_compute_workgroups_per_dimension,
+            max_push_constant_size: if features.contains(Features::PUSH_CONSTANTS) {
+                adapter.max_push_constant_size
+            } else {
+                required.max_push_constant_size
+            },
+            ..required
+        }
+    }
+}
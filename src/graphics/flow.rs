@@ -0,0 +1,166 @@
+use std::{path::PathBuf, slice, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use glam::Vec2;
+use wgpu::*;
+
+use crate::config::{ExposureSettings, PostSettings, ResizeMode};
+
+use super::{
+    post::Post,
+    region::Region,
+    renderer::Renderer,
+    sim::Simulation,
+    snapshot::{Snapshot, SnapshotFile},
+};
+
+//
+
+/// one flow field simulation on a device owned by the caller,
+/// drawn into any texture view
+///
+/// ```ignore
+/// let mut flow = Flow::new(&adapter, device, queue, &<_>::default(), format, (1280, 720))?;
+/// // every frame
+/// flow.render(&mut encoder, &view, dt);
+/// queue.submit([encoder.finish()]);
+/// ```
+pub struct Flow {
+    renderer: Renderer,
+    sim: Simulation,
+    post: Post,
+    /// seconds simulated so far
+    time: f32,
+    flags: u32,
+}
+
+/// how a [`Flow`] is set up, the same as the `[graphics]` settings
+#[derive(Debug, Clone)]
+pub struct FlowConfig {
+    /// use push constants if the device has them
+    pub push_constants: bool,
+    /// use a read-write storage texture if the device has them
+    pub storage_textures: bool,
+    /// load the shaders from here instead of the embedded ones
+    pub shader_dir: Option<PathBuf>,
+    /// accumulation texture size relative to the output
+    pub render_scale: f32,
+    /// the initial particles, `None` picks a random one
+    pub seed: Option<u64>,
+    pub resize_mode: ResizeMode,
+    pub exposure: ExposureSettings,
+    pub post: PostSettings,
+    /// how the output is composited, `Opaque` outputs alpha 1
+    pub alpha_mode: CompositeAlphaMode,
+}
+
+//
+
+impl Flow {
+    /// `adapter` is the one `device` was opened on, it is only asked for features,
+    /// `output_format` is the format of the views passed to [`Self::render`]
+    pub fn new(
+        adapter: &Adapter,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        config: &FlowConfig,
+        output_format: TextureFormat,
+        size: (u32, u32),
+    ) -> Result<Self> {
+        let size = (size.0.max(1), size.1.max(1));
+        let renderer = Renderer::new(adapter, device, queue, config, 1, 1)?;
+        let seed = config.seed.unwrap_or_else(rand::random);
+        let sim = Simulation::new(&renderer.ctx, size, seed)?;
+        let post = renderer.create_post(config, output_format, config.alpha_mode, size)?;
+
+        Ok(Self {
+            renderer,
+            sim,
+            post,
+            time: 0.0,
+            flags: 0,
+        })
+    }
+
+    /// resize the output, the accumulated image is carried over
+    pub fn resize(&mut self, size: (u32, u32)) {
+        let size = (size.0.max(1), size.1.max(1));
+        self.sim.resized(&self.renderer.ctx, size);
+        self.post.resized(&self.renderer.ctx.device, size);
+    }
+
+    /// the point pulling the particles around, in 0..1 of the output,
+    /// outside of it there is no cursor
+    pub fn set_cursor(&mut self, cursor: Vec2) {
+        self.sim.cursor = cursor;
+    }
+
+    /// the runtime toggles, the bits of the F1-F12 keys of the `flowfield` binary
+    pub fn set_flags(&mut self, flags: u32) {
+        self.flags = flags;
+        self.renderer.set_flags(flags);
+    }
+
+    /// read the simulation back, see [`SnapshotFile::save`]
+    pub fn snapshot(&self) -> Result<SnapshotFile> {
+        Ok(SnapshotFile {
+            time: self.time,
+            flags: self.flags,
+            sims: vec![self.sim.snapshot(&self.renderer.ctx)?],
+        })
+    }
+
+    /// continue from a snapshot, the image is resampled if it has another size
+    pub fn restore(&mut self, file: &SnapshotFile) -> Result<()> {
+        let snapshot: &Snapshot = file
+            .sims
+            .first()
+            .ok_or_else(|| anyhow!("the snapshot has no simulations"))?;
+        self.sim.restore(&self.renderer.ctx, snapshot)?;
+
+        self.time = file.time;
+        self.set_flags(file.flags);
+        Ok(())
+    }
+
+    /// rebuild the pipelines from `FlowConfig::shader_dir`
+    pub fn reload_shaders(&mut self) {
+        self.renderer
+            .reload_shaders(slice::from_mut(&mut self.sim), [&mut self.post]);
+    }
+
+    /// advance the simulation by `dt` seconds and draw it into `output`,
+    /// call once per submit of `encoder`
+    pub fn render(&mut self, encoder: &mut CommandEncoder, output: &TextureView, dt: f32) {
+        self.time += dt;
+
+        let queue = &self.renderer.ctx.queue;
+        self.renderer
+            .encode_sims(encoder, slice::from_ref(&self.sim), self.time, None);
+        self.sim.exposure().encode(queue, encoder, dt);
+        self.renderer.encode_blit(
+            encoder,
+            &self.sim,
+            Region::FULL,
+            self.post.scene_view(),
+            None,
+        );
+        self.post.encode(queue, encoder, output, self.time);
+    }
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            push_constants: true,
+            storage_textures: true,
+            shader_dir: None,
+            render_scale: 1.0,
+            seed: None,
+            resize_mode: <_>::default(),
+            exposure: <_>::default(),
+            post: <_>::default(),
+            alpha_mode: CompositeAlphaMode::Opaque,
+        }
+    }
+}
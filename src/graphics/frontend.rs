@@ -0,0 +1,612 @@
+use std::{
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use glam::{Mat4, Vec2};
+use wgpu::*;
+use winit::{
+    dpi::PhysicalSize,
+    window::{Window, WindowId},
+};
+
+use crate::{settings::GlobalSettings, RuntimeSettings};
+
+use super::{
+    adapter,
+    features::DeviceRequest,
+    recovery::DeviceHealth,
+    region::Region,
+    renderer::Renderer,
+    sim::Simulation,
+    snapshot::{Snapshot, SnapshotFile},
+    surface::SurfaceBuilder,
+    timing::GpuTimer,
+    view::View,
+    watcher::ShaderWatcher,
+};
+
+use bytemuck::{Pod, Zeroable};
+
+//
+
+pub struct Graphics {
+    renderer: Renderer,
+    sims: Vec<Simulation>,
+    /// one per window, the first one is the primary window
+    views: Vec<View>,
+
+    /// kept to recreate everything after a GPU error
+    settings: GlobalSettings,
+    health: DeviceHealth,
+
+    boot: Instant,
+    last_frame: Instant,
+    value: f32,
+
+    /// times the passes of the first simulation and view
+    timer: Option<GpuTimer>,
+    shader_watcher: Option<ShaderWatcher>,
+    // last_flags: u32,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct DrawPush {
+    mvp: Mat4,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct Vertex {
+    // col: Vec4,
+    // pos: Vec2,
+    // _pad: Vec2,
+}
+
+//
+
+impl Graphics {
+    /// tries to recreate the device, a driver reset can take a few seconds
+    const RECOVERY_ATTEMPTS: u32 = 4;
+    /// the first wait between the attempts, doubled after each one
+    const RECOVERY_DELAY: Duration = Duration::from_millis(500);
+
+    /// `windows` are the primary window and the extra ones
+    pub async fn init(settings: &GlobalSettings, windows: Vec<Arc<Window>>) -> Result<Self> {
+        Self::create(settings, windows, &[]).await
+    }
+
+    /// save the settings and the simulations (unless the device is already gone),
+    /// then recreate the instance, device and everything on it and resume,
+    /// retrying with a backoff, once every attempt failed the simulations
+    /// are saved to the snapshot file for `--load` and the error is returned
+    pub fn recover(self, runtime: &RuntimeSettings) -> Result<Self> {
+        tracing::warn!("recreating the GPU device");
+
+        self.settings.autosave();
+
+        let snapshots = if self.health.lost() {
+            Vec::new()
+        } else {
+            self.sims
+                .iter()
+                .map(|sim| sim.snapshot(&self.renderer.ctx))
+                .collect::<Result<Vec<_>>>()
+                .map_err(|err| tracing::warn!("Failed to save the simulations: {err}"))
+                .unwrap_or_default()
+        };
+
+        let settings = self.settings.clone();
+        let windows = self.windows();
+        let boot = self.boot;
+        let cursors: Vec<_> = self.sims.iter().map(|sim| sim.cursor).collect();
+
+        // a window can only have one surface configured at a time
+        drop(self);
+
+        let mut delay = Self::RECOVERY_DELAY;
+        let mut attempt = 1;
+        let mut graphics = loop {
+            match pollster::block_on(Self::create(&settings, windows.clone(), &snapshots)) {
+                Ok(graphics) => break graphics,
+                Err(err) if attempt < Self::RECOVERY_ATTEMPTS => {
+                    tracing::warn!(
+                        "Failed to recreate the GPU device, retrying in {delay:?}: {err}"
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    let file = SnapshotFile {
+                        time: boot.elapsed().as_secs_f32(),
+                        flags: runtime.f,
+                        sims: snapshots,
+                    };
+                    match settings.snapshot.path() {
+                        Some(path) if !file.sims.is_empty() => match file.save(&path) {
+                            Ok(()) => tracing::info!("continue with --load {path:?}"),
+                            Err(err) => tracing::error!("Failed to save the simulations: {err}"),
+                        },
+                        _ => {}
+                    }
+                    return Err(err.context("Failed to recreate the GPU device"));
+                }
+            }
+        };
+        graphics.boot = boot;
+        for (sim, cursor) in graphics.sims.iter_mut().zip(cursors) {
+            sim.cursor = cursor;
+        }
+
+        tracing::info!("recovered from the GPU error");
+        Ok(graphics)
+    }
+
+    /// true after the device was lost or ran out of memory,
+    /// [`Self::recover`] has to be called before the next frame
+    pub fn needs_recovery(&self) -> bool {
+        self.health.needs_recovery()
+    }
+
+    /// save every simulation, the clock and the runtime `flags` to `path`
+    pub fn save_snapshot(&self, path: &Path, flags: u32) -> Result<()> {
+        let sims = self
+            .sims
+            .iter()
+            .map(|sim| sim.snapshot(&self.renderer.ctx))
+            .collect::<Result<_>>()?;
+
+        SnapshotFile {
+            time: self.boot.elapsed().as_secs_f32(),
+            flags,
+            sims,
+        }
+        .save(path)
+    }
+
+    /// restore the simulations from a file saved with [`Self::save_snapshot`],
+    /// returns the runtime flags it was saved with
+    pub fn load_snapshot(&mut self, path: &Path) -> Result<u32> {
+        let file = SnapshotFile::load(path)?;
+        if file.sims.len() != self.sims.len() {
+            tracing::warn!(
+                "the snapshot has {} simulations, restoring {} of them",
+                file.sims.len(),
+                file.sims.len().min(self.sims.len())
+            );
+        }
+
+        for (sim, snapshot) in self.sims.iter_mut().zip(file.sims.iter()) {
+            sim.restore(&self.renderer.ctx, snapshot)?;
+        }
+
+        let now = Instant::now();
+        self.boot = Duration::try_from_secs_f32(file.time)
+            .ok()
+            .and_then(|time| now.checked_sub(time))
+            .unwrap_or(now);
+        Ok(file.flags)
+    }
+
+    fn windows(&self) -> Vec<Arc<Window>> {
+        self.views
+            .iter()
+            .map(|view| view.surface.window.clone())
+            .collect()
+    }
+
+    async fn create(
+        settings: &GlobalSettings,
+        windows: Vec<Arc<Window>>,
+        snapshots: &[Snapshot],
+    ) -> Result<Self> {
+        let s = &settings.graphics;
+
+        let primary = windows
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow!("no window to draw to"))?;
+        let (instance, surface_builder, gpu) = adapter::request(s, primary).await?;
+
+        // the adapter is picked for the primary window, the others have to work with it
+        let mut surface_builders = vec![surface_builder];
+        for window in windows.iter().skip(1) {
+            let surface_builder = SurfaceBuilder::new(instance.clone(), window.clone())?;
+            if !gpu.is_surface_supported(&surface_builder.surface) {
+                bail!("{} can't draw to every window", gpu.get_info().name);
+            }
+            surface_builders.push(surface_builder);
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        {
+            let inst = instance.clone();
+            thread::spawn(move || {
+                inst.poll_all(true);
+            });
+        }
+
+        /* let features = Features::POLYGON_MODE_LINE | Features::PUSH_CONSTANTS;
+        let limits = Limits {
+            max_texture_dimension_2d: 128,
+            max_push_constant_size: core::mem::size_of::<Push>() as u32,
+            ..Limits::downlevel_defaults()
+        }; */
+        let request = DeviceRequest::negotiate(&gpu, s)?;
+
+        let (device, queue) = gpu
+            .request_device(
+                &DeviceDescriptor {
+                    label: None,
+                    required_features: request.features,
+                    required_limits: request.limits,
+                },
+                None,
+            )
+            .await
+            .map_err(|err| anyhow!("Failed to open {}: {err}", gpu.get_info().name))?;
+        let device = Arc::new(device);
+        let health = DeviceHealth::watch(&device);
+
+        let timer = device
+            .features()
+            .contains(Features::TIMESTAMP_QUERY)
+            .then(|| GpuTimer::new(&device, &queue));
+
+        // every view is drawn in the same submit, every simulation too
+        let sim_count = if settings.window.shared_simulation {
+            1
+        } else {
+            windows.len()
+        };
+
+        let config = s.to_flow_config();
+        let renderer = Renderer::new(
+            &gpu,
+            device.clone(),
+            Arc::new(queue),
+            &config,
+            sim_count as u32,
+            windows.len() as u32,
+        )?;
+
+        let mut views = surface_builders
+            .into_iter()
+            .enumerate()
+            .map(|(i, surface_builder)| {
+                // the window can be minimized when the device is recreated,
+                // it gets resized to the real size once it is restored
+                let PhysicalSize { width, height } = surface_builder.window.inner_size();
+                let size = (width.max(1), height.max(1));
+
+                let surface = surface_builder.build(s, &gpu, device.clone());
+                let post =
+                    renderer.create_post(&config, surface.format(), surface.alpha_mode(), size)?;
+
+                Ok(View {
+                    surface,
+                    post,
+                    size,
+                    sim: i % sim_count,
+                    region: Region::FULL,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // only shaders loaded from disk can change
+        let shader_watcher = match &s.shader_dir {
+            Some(dir) if s.shader_hot_reload => ShaderWatcher::new(dir)
+                .map_err(|err| tracing::error!("Failed to watch the shaders: {err}"))
+                .ok(),
+            Some(dir) => {
+                tracing::info!("using shaders from {dir:?}");
+                None
+            }
+            None => {
+                tracing::info!("using the embedded shaders");
+                None
+            }
+        };
+
+        let seed = s.seed.unwrap_or_else(rand::random);
+        let mut sims = Self::layout(&mut views, &vec![(1, 1); sim_count])
+            .into_iter()
+            .enumerate()
+            .map(|(i, size)| Simulation::new(&renderer.ctx, size, seed.wrapping_add(i as u64)))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (sim, snapshot) in sims.iter_mut().zip(snapshots.iter()) {
+            if let Err(err) = sim.restore(&renderer.ctx, snapshot) {
+                tracing::warn!("Failed to restore the simulation: {err}");
+            }
+        }
+
+        Ok(Self {
+            renderer,
+            sims,
+            views,
+
+            settings: settings.clone(),
+            health,
+
+            boot: Instant::now(),
+            last_frame: Instant::now(),
+            value: 0.0,
+
+            timer,
+            shader_watcher,
+            // last_flags: 0,
+        })
+    }
+
+    /// rebuild every pipeline from the shader files,
+    /// the pipelines that fail to compile keep their last good version
+    pub fn reload_shaders(&mut self) {
+        self.renderer.reload_shaders(
+            &mut self.sims,
+            self.views.iter_mut().map(|view| &mut view.post),
+        );
+    }
+
+    /// place the views of each simulation like their windows are placed on the desktop,
+    /// or side by side if the positions are unknown, returns the domain size of each simulation,
+    /// at least `min_sizes`
+    fn layout(views: &mut [View], min_sizes: &[(u32, u32)]) -> Vec<(u32, u32)> {
+        (0..min_sizes.len())
+            .map(|sim| {
+                let views: Vec<_> = views.iter_mut().filter(|view| view.sim == sim).collect();
+
+                let positions: Option<Vec<_>> = views.iter().map(|view| view.position()).collect();
+                let positions = positions.unwrap_or_else(|| {
+                    views
+                        .iter()
+                        .scan(0, |x, view| {
+                            let position = (*x, 0);
+                            *x += view.size.0 as i32;
+                            Some(position)
+                        })
+                        .collect()
+                });
+
+                let rects: Vec<_> = positions
+                    .into_iter()
+                    .zip(views.iter().map(|view| view.size))
+                    .collect();
+                let (size, regions) = Region::layout(&rects, min_sizes[sim]);
+
+                for (view, region) in views.into_iter().zip(regions) {
+                    view.region = region;
+                }
+                size
+            })
+            .collect()
+    }
+
+    /// update the regions after a window moved or resized,
+    /// resizing the simulations whose domain changed,
+    /// with `grow_only` the domains never shrink
+    fn relayout(&mut self, grow_only: bool) {
+        let min_sizes: Vec<_> = self
+            .sims
+            .iter()
+            .map(|sim| if grow_only { sim.size() } else { (1, 1) })
+            .collect();
+        let sizes = Self::layout(&mut self.views, &min_sizes);
+        for (sim, size) in self.sims.iter_mut().zip(sizes) {
+            if sim.size() != size {
+                sim.resized(&self.renderer.ctx, size);
+            }
+        }
+    }
+
+    fn view_mut(&mut self, window: WindowId) -> Option<&mut View> {
+        self.views.iter_mut().find(|view| view.id() == window)
+    }
+
+    /// switch every window to the next present mode its surface supports
+    pub fn cycle_present_mode(&mut self) {
+        for view in self.views.iter_mut() {
+            view.surface.cycle_present_mode();
+        }
+    }
+
+    pub fn scrolled(&mut self, delta: (f32, f32)) {
+        self.value += delta.0 + delta.1;
+        tracing::debug!("value: {}", self.value);
+    }
+
+    /// `position` is in the window pixels of `window`
+    pub fn cursor_moved(&mut self, window: WindowId, position: Vec2) {
+        let Some(view) = self.views.iter().find(|view| view.id() == window) else {
+            return;
+        };
+        self.sims[view.sim].cursor = view.to_domain(position);
+    }
+
+    /// the regions follow the windows across the desktop,
+    /// the image is only resampled when the domain grows, not on every step of a drag
+    pub fn moved(&mut self, window: WindowId) {
+        if self.views.len() > 1 && self.view_mut(window).is_some() {
+            self.relayout(true);
+        }
+    }
+
+    pub fn resized(&mut self, window: WindowId, size: (u32, u32)) {
+        let device = self.renderer.ctx.device.clone();
+        let Some(view) = self.view_mut(window) else {
+            return;
+        };
+        view.surface.configure(Some(size));
+
+        // minimized, keep the old size until the window is restored
+        if view.surface.suspended() {
+            return;
+        }
+
+        view.post.resized(&device, size);
+        view.size = size;
+
+        self.relayout(false);
+
+        // let (width, height) = size;
+        /* self.draw_target = self.device.create_texture_with_data(
+            &self.queue,
+            &TextureDescriptor {
+                label: None,
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: self.surface.format(),
+                usage: TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+                view_formats: &[self.surface.format()],
+            },
+            &(0..width * height * 4).map(|_| 0u8).collect::<Vec<_>>(),
+        ); */
+    }
+
+    /// compile the requested variant and pick up shader changes
+    fn prepare(&mut self, settings: &RuntimeSettings) {
+        self.renderer.set_flags(settings.f);
+
+        if self
+            .shader_watcher
+            .as_ref()
+            .is_some_and(|watcher| watcher.changed())
+        {
+            self.reload_shaders();
+        }
+    }
+
+    fn encode_sims(&self, encoder: &mut CommandEncoder) {
+        self.renderer.encode_sims(
+            encoder,
+            &self.sims,
+            self.boot.elapsed().as_secs_f32(),
+            self.timer.as_ref(),
+        );
+    }
+
+    /// advance the simulations without drawing to the windows,
+    /// used while every window is minimized
+    pub fn simulate(&mut self, settings: &RuntimeSettings) {
+        if self.health.needs_recovery() {
+            return;
+        }
+
+        self.prepare(settings);
+
+        let mut encoder = self
+            .renderer
+            .ctx
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { ..<_>::default() });
+        self.encode_sims(&mut encoder);
+        self.renderer.ctx.queue.submit([encoder.finish()]);
+    }
+
+    pub fn frame(&mut self, settings: &RuntimeSettings) {
+        /* if self.last_flags != settings.f {
+            self.last_flags = settings.f;
+
+            self.resized(self.size);
+        } */
+        if settings.f & 1 << 11 != 0 {
+            // self.resized(self.size);
+        }
+
+        if self.health.needs_recovery() {
+            return;
+        }
+
+        self.prepare(settings);
+        // println!("flags: {:b}", settings.f);
+
+        // minimized windows have nothing to draw to
+        let mut frames = Vec::new();
+        for (i, view) in self.views.iter_mut().enumerate() {
+            match view.surface.try_acquire() {
+                Ok(Some(texture)) => frames.push((i, texture)),
+                // the surface was reconfigured, try again on the next redraw
+                Ok(None) if !view.surface.suspended() => view.surface.window.request_redraw(),
+                Ok(None) => {}
+                Err(err) if matches!(err.downcast_ref(), Some(SurfaceError::OutOfMemory)) => {
+                    self.health.set_out_of_memory();
+                    return;
+                }
+                Err(err) => {
+                    tracing::error!("Failed to acquire the next frame: {err}");
+                    self.health.set_failed();
+                    return;
+                }
+            }
+        }
+        if frames.is_empty() {
+            return;
+        }
+
+        let mut encoder = self
+            .renderer
+            .ctx
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { ..<_>::default() });
+
+        self.encode_sims(&mut encoder);
+
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        for sim in self.sims.iter() {
+            sim.exposure()
+                .encode(&self.renderer.ctx.queue, &mut encoder, dt);
+        }
+
+        for (n, (i, texture)) in frames.iter().enumerate() {
+            let view = &self.views[*i];
+            let sim = &self.sims[view.sim];
+            let texture_view = texture
+                .texture
+                .create_view(&TextureViewDescriptor { ..<_>::default() });
+
+            self.renderer.encode_blit(
+                &mut encoder,
+                sim,
+                view.region,
+                view.post.scene_view(),
+                self.timer.as_ref().filter(|_| n == 0),
+            );
+
+            view.post.encode(
+                &self.renderer.ctx.queue,
+                &mut encoder,
+                &texture_view,
+                self.boot.elapsed().as_secs_f32(),
+            );
+        }
+
+        if let Some(timer) = self.timer.as_ref() {
+            timer.resolve(&mut encoder);
+        }
+
+        self.renderer.ctx.queue.submit([encoder.finish()]);
+
+        if let Some(timer) = self.timer.as_mut() {
+            timer.collect(&self.renderer.ctx.device);
+        }
+
+        for (i, texture) in frames {
+            texture.present();
+            self.views[i].surface.window.set_visible(true);
+        }
+    }
+}
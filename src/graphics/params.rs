@@ -0,0 +1,214 @@
+use std::{cell::Cell, marker::PhantomData, mem::size_of, num::NonZeroU64};
+
+use bytemuck::Pod;
+use wgpu::*;
+
+//
+
+/// how the small per-pass parameters reach the shaders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamMode {
+    /// `var<push_constant>`, needs [`Features::PUSH_CONSTANTS`]
+    PushConstants,
+    /// a dynamic offset uniform buffer, one slot per pass,
+    /// for WebGPU and drivers without push constants
+    Uniform,
+}
+
+/// parameters of type `T` for the pipelines sharing one layout
+///
+/// in [`ParamMode::Uniform`] the shader declares
+/// `@group(N) @binding(0) var<uniform>` instead of `var<push_constant>`,
+/// `N` being the bind group after every other bind group of the layout
+pub struct Params<T> {
+    stages: ShaderStages,
+    push_constant_range: Option<PushConstantRange>,
+    uniform: Option<UniformParams>,
+    _marker: PhantomData<T>,
+}
+
+struct UniformParams {
+    group: u32,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    buffer: Buffer,
+    stride: u32,
+    slots: u32,
+    next: Cell<u32>,
+}
+
+/// compute and render passes
+pub trait ParamPass<'a> {
+    fn push(&mut self, stages: ShaderStages, data: &[u8]);
+
+    fn bind(&mut self, group: u32, bind_group: &'a BindGroup, offset: u32);
+}
+
+//
+
+impl ParamMode {
+    /// the largest push constant struct in the shaders fits in this
+    const MIN_PUSH_CONSTANT_SIZE: u32 = 128;
+
+    /// push constants if the device supports them and `allowed` is set
+    pub fn new(device: &Device, allowed: bool) -> Self {
+        let supported = device.features().contains(Features::PUSH_CONSTANTS)
+            && device.limits().max_push_constant_size >= Self::MIN_PUSH_CONSTANT_SIZE;
+
+        if allowed && supported {
+            Self::PushConstants
+        } else {
+            Self::Uniform
+        }
+    }
+
+    /// the preprocessor define selecting the matching shader declarations
+    pub fn define(self) -> Option<&'static str> {
+        match self {
+            Self::PushConstants => None,
+            Self::Uniform => Some("PARAMS_UNIFORM"),
+        }
+    }
+}
+
+impl<T: Pod> Params<T> {
+    /// `slots` is the most times [`Params::set`] is called per submit,
+    /// the uniform buffer is a ring of that many slots
+    pub fn new(
+        device: &Device,
+        mode: ParamMode,
+        stages: ShaderStages,
+        group: u32,
+        slots: u32,
+    ) -> Self {
+        let size = size_of::<T>() as u32;
+
+        let (push_constant_range, uniform) = match mode {
+            ParamMode::PushConstants => (
+                Some(PushConstantRange {
+                    stages,
+                    range: 0..size,
+                }),
+                None,
+            ),
+            ParamMode::Uniform => {
+                let slots = slots.max(1);
+                let stride =
+                    size.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment);
+
+                let bind_group_layout =
+                    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                        label: Some("params bind group layout"),
+                        entries: &[BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: stages,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: true,
+                                min_binding_size: NonZeroU64::new(size as u64),
+                            },
+                            count: None,
+                        }],
+                    });
+
+                let buffer = device.create_buffer(&BufferDescriptor {
+                    label: Some("params"),
+                    size: stride as u64 * slots as u64,
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+
+                let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("params bind group"),
+                    layout: &bind_group_layout,
+                    entries: &[BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::Buffer(BufferBinding {
+                            buffer: &buffer,
+                            offset: 0,
+                            size: NonZeroU64::new(size as u64),
+                        }),
+                    }],
+                });
+
+                (
+                    None,
+                    Some(UniformParams {
+                        group,
+                        bind_group_layout,
+                        bind_group,
+                        buffer,
+                        stride,
+                        slots,
+                        next: Cell::new(0),
+                    }),
+                )
+            }
+        };
+
+        Self {
+            stages,
+            push_constant_range,
+            uniform,
+            _marker: PhantomData,
+        }
+    }
+
+    /// for [`PipelineLayoutDescriptor::push_constant_ranges`]
+    pub fn push_constant_ranges(&self) -> &[PushConstantRange] {
+        self.push_constant_range.as_slice()
+    }
+
+    /// `layouts` followed by the uniform buffer layout if there is one,
+    /// for [`PipelineLayoutDescriptor::bind_group_layouts`]
+    pub fn bind_group_layouts<'a>(
+        &'a self,
+        layouts: &[&'a BindGroupLayout],
+    ) -> Vec<&'a BindGroupLayout> {
+        let mut layouts = layouts.to_vec();
+        if let Some(uniform) = self.uniform.as_ref() {
+            debug_assert_eq!(layouts.len(), uniform.group as usize);
+            layouts.push(&uniform.bind_group_layout);
+        }
+        layouts
+    }
+
+    /// set the parameters of the next draw or dispatch in `pass`
+    pub fn set<'a>(&'a self, queue: &Queue, pass: &mut impl ParamPass<'a>, value: &T) {
+        let data = bytemuck::bytes_of(value);
+
+        let Some(uniform) = self.uniform.as_ref() else {
+            pass.push(self.stages, data);
+            return;
+        };
+
+        // every slot is written before the submit, so the ring
+        // only wraps around between submits
+        let slot = uniform.next.get();
+        uniform.next.set((slot + 1) % uniform.slots);
+
+        let offset = slot * uniform.stride;
+        queue.write_buffer(&uniform.buffer, offset as u64, data);
+        pass.bind(uniform.group, &uniform.bind_group, offset);
+    }
+}
+
+impl<'a> ParamPass<'a> for ComputePass<'a> {
+    fn push(&mut self, _: ShaderStages, data: &[u8]) {
+        self.set_push_constants(0, data);
+    }
+
+    fn bind(&mut self, group: u32, bind_group: &'a BindGroup, offset: u32) {
+        self.set_bind_group(group, bind_group, &[offset]);
+    }
+}
+
+impl<'a> ParamPass<'a> for RenderPass<'a> {
+    fn push(&mut self, stages: ShaderStages, data: &[u8]) {
+        self.set_push_constants(stages, 0, data);
+    }
+
+    fn bind(&mut self, group: u32, bind_group: &'a BindGroup, offset: u32) {
+        self.set_bind_group(group, bind_group, &[offset]);
+    }
+}
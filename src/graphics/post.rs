@@ -0,0 +1,597 @@
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec4};
+use wgpu::*;
+
+use crate::config::{PostPass, PostSettings};
+
+use super::{
+    params::{ParamMode, Params},
+    preprocess::Preprocessor,
+    shaders::{load_module, validated},
+};
+
+//
+
+/// ordered chain of fullscreen post passes
+///
+/// the blit pass renders into [`Post::scene_view`],
+/// the passes ping-pong between two scene textures
+/// and the last one is drawn into the output view,
+/// converting to the alpha mode and transfer function of the surface
+pub struct Post {
+    settings: PostSettings,
+
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    composite_bind_group_layout: BindGroupLayout,
+    params: Params<PostPush>,
+    layout: PipelineLayout,
+    composite_layout: PipelineLayout,
+    output_format: TextureFormat,
+    output_params: Vec4,
+    pipelines: PostPipelines,
+
+    targets: PostTargets,
+}
+
+struct PostPipelines {
+    blur: RenderPipeline,
+    threshold: RenderPipeline,
+    downsample: RenderPipeline,
+    upsample: RenderPipeline,
+    composite: RenderPipeline,
+    vignette: RenderPipeline,
+    chromatic: RenderPipeline,
+    grain: RenderPipeline,
+    output: RenderPipeline,
+}
+
+/// the size dependent resources
+struct PostTargets {
+    size: (u32, u32),
+
+    scene_views: [TextureView; 2],
+    scene_bind_groups: [BindGroup; 2],
+    composite_bind_groups: [BindGroup; 2],
+
+    bloom_sizes: Vec<(u32, u32)>,
+    bloom_views: Vec<TextureView>,
+    bloom_bind_groups: Vec<BindGroup>,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub(super) struct PostPush {
+    pub texel: Vec2,
+    pub dir: Vec2,
+    pub params: Vec4,
+}
+
+//
+
+impl Post {
+    /// format of the intermediate scene textures
+    pub const FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+    pub fn new(
+        device: &Device,
+        shaders: &Preprocessor,
+        param_mode: ParamMode,
+        settings: PostSettings,
+        output_format: TextureFormat,
+        alpha_mode: CompositeAlphaMode,
+        size: (u32, u32),
+    ) -> Result<Self> {
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("post sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            ..<_>::default()
+        });
+
+        let texture = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[texture(0), sampler_entry],
+        });
+        let composite_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[texture(0), sampler_entry, texture(2)],
+            });
+
+        let params = Params::new(
+            device,
+            param_mode,
+            ShaderStages::FRAGMENT,
+            1,
+            Self::max_passes(&settings),
+        );
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &params.bind_group_layouts(&[&bind_group_layout]),
+            push_constant_ranges: params.push_constant_ranges(),
+        });
+        let composite_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &params.bind_group_layouts(&[&composite_bind_group_layout]),
+            push_constant_ranges: params.push_constant_ranges(),
+        });
+
+        let pipelines =
+            PostPipelines::new(device, shaders, &layout, &composite_layout, output_format)?;
+
+        let targets = PostTargets::new(
+            device,
+            &settings,
+            &sampler,
+            &bind_group_layout,
+            &composite_bind_group_layout,
+            size,
+        );
+
+        Ok(Self {
+            settings,
+
+            sampler,
+            bind_group_layout,
+            composite_bind_group_layout,
+            params,
+            layout,
+            composite_layout,
+            output_format,
+            output_params: Self::output_params(output_format, alpha_mode),
+            pipelines,
+
+            targets,
+        })
+    }
+
+    /// parameters of `fs_output`: (alpha mode, sRGB encode, _, _)
+    fn output_params(format: TextureFormat, alpha_mode: CompositeAlphaMode) -> Vec4 {
+        // the scene textures are premultiplied
+        let alpha = match alpha_mode {
+            CompositeAlphaMode::Opaque => 0.0,
+            CompositeAlphaMode::PostMultiplied => 2.0,
+            _ => 1.0,
+        };
+
+        // sRGB formats encode in hardware, float formats are linear
+        let encode = !format.is_srgb()
+            && !matches!(
+                format,
+                TextureFormat::Rgba16Float | TextureFormat::Rgba32Float
+            );
+
+        Vec4::new(alpha, if encode { 1.0 } else { 0.0 }, 0.0, 0.0)
+    }
+
+    /// upper bound of the passes [`Post::encode`] draws
+    fn max_passes(settings: &PostSettings) -> u32 {
+        // threshold, down and up for every level and composite
+        let bloom = 2 + 2 * settings.bloom.levels.min(32);
+        2 + settings.order.len() as u32 * bloom.max(2)
+    }
+
+    /// recompile the shader, keeping the old pipelines on failure
+    pub fn reload(&mut self, device: &Device, shaders: &Preprocessor) -> Result<()> {
+        self.pipelines = PostPipelines::new(
+            device,
+            shaders,
+            &self.layout,
+            &self.composite_layout,
+            self.output_format,
+        )?;
+        Ok(())
+    }
+
+    /// the render target of the blit pass
+    pub fn scene_view(&self) -> &TextureView {
+        &self.targets.scene_views[0]
+    }
+
+    pub fn resized(&mut self, device: &Device, size: (u32, u32)) {
+        self.targets = PostTargets::new(
+            device,
+            &self.settings,
+            &self.sampler,
+            &self.bind_group_layout,
+            &self.composite_bind_group_layout,
+            size,
+        );
+    }
+
+    pub fn encode(
+        &self,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        output: &TextureView,
+        time: f32,
+    ) {
+        let s = &self.settings;
+        let t = &self.targets;
+        let texel = |(width, height): (u32, u32)| 1.0 / Vec2::new(width as f32, height as f32);
+        let scene_texel = texel(t.size);
+
+        let mut current = 0;
+
+        for pass in s.order.iter() {
+            match pass {
+                PostPass::Blur if s.blur.enabled => {
+                    for dir in [Vec2::X, Vec2::Y] {
+                        self.draw(
+                            queue,
+                            encoder,
+                            &self.pipelines.blur,
+                            &t.scene_bind_groups[current],
+                            &t.scene_views[1 - current],
+                            PostPush {
+                                texel: scene_texel,
+                                dir,
+                                params: Vec4::new(s.blur.sigma, 0.0, 0.0, 0.0),
+                            },
+                            true,
+                        );
+                        current = 1 - current;
+                    }
+                }
+                PostPass::Bloom if s.bloom.enabled => {
+                    self.draw(
+                        queue,
+                        encoder,
+                        &self.pipelines.threshold,
+                        &t.scene_bind_groups[current],
+                        &t.bloom_views[0],
+                        PostPush {
+                            texel: scene_texel,
+                            dir: Vec2::ZERO,
+                            params: Vec4::new(s.bloom.threshold, s.bloom.knee, 0.0, 0.0),
+                        },
+                        true,
+                    );
+
+                    for mip in 1..t.bloom_views.len() {
+                        self.draw(
+                            queue,
+                            encoder,
+                            &self.pipelines.downsample,
+                            &t.bloom_bind_groups[mip - 1],
+                            &t.bloom_views[mip],
+                            PostPush {
+                                texel: texel(t.bloom_sizes[mip - 1]),
+                                dir: Vec2::ZERO,
+                                params: Vec4::ZERO,
+                            },
+                            true,
+                        );
+                    }
+
+                    for mip in (1..t.bloom_views.len()).rev() {
+                        self.draw(
+                            queue,
+                            encoder,
+                            &self.pipelines.upsample,
+                            &t.bloom_bind_groups[mip],
+                            &t.bloom_views[mip - 1],
+                            PostPush {
+                                texel: texel(t.bloom_sizes[mip]),
+                                dir: Vec2::ZERO,
+                                params: Vec4::ZERO,
+                            },
+                            false,
+                        );
+                    }
+
+                    self.draw(
+                        queue,
+                        encoder,
+                        &self.pipelines.composite,
+                        &t.composite_bind_groups[current],
+                        &t.scene_views[1 - current],
+                        PostPush {
+                            texel: scene_texel,
+                            dir: Vec2::ZERO,
+                            params: Vec4::new(s.bloom.intensity, 0.0, 0.0, 0.0),
+                        },
+                        true,
+                    );
+                    current = 1 - current;
+                }
+                PostPass::Vignette if s.vignette.enabled => {
+                    let v = &s.vignette;
+                    self.draw(
+                        queue,
+                        encoder,
+                        &self.pipelines.vignette,
+                        &t.scene_bind_groups[current],
+                        &t.scene_views[1 - current],
+                        PostPush {
+                            texel: scene_texel,
+                            dir: Vec2::ZERO,
+                            params: Vec4::new(v.strength, v.radius, v.softness, 0.0),
+                        },
+                        true,
+                    );
+                    current = 1 - current;
+                }
+                PostPass::Chromatic if s.chromatic.enabled => {
+                    self.draw(
+                        queue,
+                        encoder,
+                        &self.pipelines.chromatic,
+                        &t.scene_bind_groups[current],
+                        &t.scene_views[1 - current],
+                        PostPush {
+                            texel: scene_texel,
+                            dir: Vec2::ZERO,
+                            params: Vec4::new(s.chromatic.offset, 0.0, 0.0, 0.0),
+                        },
+                        true,
+                    );
+                    current = 1 - current;
+                }
+                PostPass::Grain if s.grain.enabled => {
+                    self.draw(
+                        queue,
+                        encoder,
+                        &self.pipelines.grain,
+                        &t.scene_bind_groups[current],
+                        &t.scene_views[1 - current],
+                        PostPush {
+                            texel: scene_texel,
+                            dir: Vec2::ZERO,
+                            params: Vec4::new(s.grain.strength, time, 0.0, 0.0),
+                        },
+                        true,
+                    );
+                    current = 1 - current;
+                }
+                _ => {}
+            }
+        }
+
+        self.draw(
+            queue,
+            encoder,
+            &self.pipelines.output,
+            &t.scene_bind_groups[current],
+            output,
+            PostPush {
+                texel: scene_texel,
+                dir: Vec2::ZERO,
+                params: self.output_params,
+            },
+            true,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(
+        &self,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        pipeline: &RenderPipeline,
+        bind_group: &BindGroup,
+        target: &TextureView,
+        push: PostPush,
+        clear: bool,
+    ) {
+        let load = if clear {
+            LoadOp::Clear(Color::TRANSPARENT)
+        } else {
+            LoadOp::Load
+        };
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("post pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load,
+                    store: StoreOp::Store,
+                },
+            })],
+            ..<_>::default()
+        });
+
+        pass.set_pipeline(pipeline);
+        self.params.set(queue, &mut pass, &push);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.draw(0..4, 0..1);
+    }
+}
+
+impl PostPipelines {
+    fn new(
+        device: &Device,
+        shaders: &Preprocessor,
+        layout: &PipelineLayout,
+        composite_layout: &PipelineLayout,
+        output_format: TextureFormat,
+    ) -> Result<Self> {
+        validated(device, || {
+            let module = load_module(device, shaders, "post.wgsl")?;
+
+            let pipeline = |entry_point: &str,
+                            layout: &PipelineLayout,
+                            format: TextureFormat,
+                            blend: Option<BlendState>| {
+                device.create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(layout),
+                    vertex: VertexState {
+                        module: &module,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    primitive: PrimitiveState {
+                        topology: PrimitiveTopology::TriangleStrip,
+                        ..<_>::default()
+                    },
+                    depth_stencil: None,
+                    multisample: <_>::default(),
+                    fragment: Some(FragmentState {
+                        module: &module,
+                        entry_point,
+                        targets: &[Some(ColorTargetState {
+                            format,
+                            blend,
+                            write_mask: ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                })
+            };
+
+            let additive = BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+            };
+
+            let format = Post::FORMAT;
+            Ok(Self {
+                blur: pipeline("fs_blur", layout, format, None),
+                threshold: pipeline("fs_threshold", layout, format, None),
+                downsample: pipeline("fs_downsample", layout, format, None),
+                upsample: pipeline("fs_upsample", layout, format, Some(additive)),
+                composite: pipeline("fs_composite", composite_layout, format, None),
+                vignette: pipeline("fs_vignette", layout, format, None),
+                chromatic: pipeline("fs_chromatic", layout, format, None),
+                grain: pipeline("fs_grain", layout, format, None),
+                output: pipeline("fs_output", layout, output_format, None),
+            })
+        })
+    }
+}
+
+impl PostTargets {
+    fn new(
+        device: &Device,
+        settings: &PostSettings,
+        sampler: &Sampler,
+        layout: &BindGroupLayout,
+        composite_layout: &BindGroupLayout,
+        (width, height): (u32, u32),
+    ) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+
+        let texture = |label, width, height, mip_level_count| {
+            device.create_texture(&TextureDescriptor {
+                label: Some(label),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: Post::FORMAT,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+        };
+
+        let bind_group = |view: &TextureView, bloom: Option<&TextureView>| {
+            let mut entries = vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ];
+            if let Some(bloom) = bloom {
+                entries.push(BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(bloom),
+                });
+            }
+
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("post bind group"),
+                layout: if bloom.is_some() {
+                    composite_layout
+                } else {
+                    layout
+                },
+                entries: &entries,
+            })
+        };
+
+        let scene_views =
+            [(); 2].map(|_| texture("post scene", width, height, 1).create_view(&<_>::default()));
+
+        // the bloom pyramid starts at half resolution
+        // and stops before any side reaches zero
+        let (bloom_width, bloom_height) = ((width / 2).max(1), (height / 2).max(1));
+        let max_levels = 32 - bloom_width.min(bloom_height).leading_zeros();
+        let levels = settings.bloom.levels.clamp(1, max_levels);
+
+        let bloom = texture("post bloom", bloom_width, bloom_height, levels);
+        let bloom_views: Vec<_> = (0..levels)
+            .map(|mip| {
+                bloom.create_view(&TextureViewDescriptor {
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    ..<_>::default()
+                })
+            })
+            .collect();
+        let bloom_sizes = (0..levels)
+            .map(|mip| ((bloom_width >> mip).max(1), (bloom_height >> mip).max(1)))
+            .collect();
+        let bloom_bind_groups = bloom_views
+            .iter()
+            .map(|view| bind_group(view, None))
+            .collect();
+
+        let scene_bind_groups = [0, 1].map(|i| bind_group(&scene_views[i], None));
+        let composite_bind_groups =
+            [0, 1].map(|i| bind_group(&scene_views[i], Some(&bloom_views[0])));
+
+        Self {
+            size: (width, height),
+
+            scene_views,
+            scene_bind_groups,
+            composite_bind_groups,
+
+            bloom_sizes,
+            bloom_views,
+            bloom_bind_groups,
+        }
+    }
+}
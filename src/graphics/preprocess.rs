@@ -0,0 +1,340 @@
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fmt::Write,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+
+//
+
+/// WGSL preprocessor
+///
+/// directives are comments at the start of a line:
+/// - `//!include "path"` pastes a file, relative to the including file
+///   or any of the search paths, every file is included at most once
+/// - `//!define NAME` and `//!undef NAME`
+/// - `//!ifdef NAME`, `//!ifndef NAME`, `//!else` and `//!endif`
+///
+/// files are looked up from the search paths first
+/// and then from the embedded files
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    search_paths: Vec<PathBuf>,
+    embedded: &'static [(&'static str, &'static str)],
+    defines: HashSet<String>,
+}
+
+/// preprocessed source with a map back to the original files
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessed {
+    pub source: String,
+    pub line_map: LineMap,
+}
+
+/// maps the preprocessed line numbers to the original `file:line`
+#[derive(Debug, Clone, Default)]
+pub struct LineMap {
+    files: Vec<PathBuf>,
+    /// (file index, 1-based line) for every output line
+    lines: Vec<(usize, usize)>,
+}
+
+/// a resolved shader file
+struct File {
+    /// canonical path on disk, or `<embedded>/name`
+    path: PathBuf,
+    /// contents of an embedded file
+    embedded: Option<&'static str>,
+}
+
+struct State {
+    defines: HashSet<String>,
+    included: HashSet<PathBuf>,
+    stack: Vec<PathBuf>,
+    out: Preprocessed,
+}
+
+struct Conditional {
+    line: usize,
+    active: bool,
+    parent_active: bool,
+    seen_else: bool,
+}
+
+const EMBEDDED_ROOT: &str = "<embedded>";
+
+//
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn search_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.search_paths.push(path.into());
+        self
+    }
+
+    /// `(name, contents)` pairs, usually from `include_str!`
+    pub fn embedded(mut self, files: &'static [(&'static str, &'static str)]) -> Self {
+        self.embedded = files;
+        self
+    }
+
+    pub fn define(mut self, name: impl Into<String>) -> Self {
+        self.defines.insert(name.into());
+        self
+    }
+
+    /// preprocess `name`, looked up from the search paths
+    pub fn load(&self, name: &str) -> Result<Preprocessed> {
+        let file = self
+            .resolve(None, name)
+            .ok_or_else(|| anyhow!("could not find shader \"{name}\""))?;
+
+        let mut state = State {
+            defines: self.defines.clone(),
+            included: <_>::default(),
+            stack: <_>::default(),
+            out: <_>::default(),
+        };
+        self.process(&mut state, file)?;
+
+        Ok(state.out)
+    }
+
+    /// relative to the including file first, then the search paths,
+    /// an embedded file includes relative to its name in the search paths
+    /// before the embedded files, so files on disk override embedded ones
+    fn resolve(&self, from: Option<&File>, name: &str) -> Option<File> {
+        let embedded = (!self.embedded.is_empty()).then_some(Path::new(EMBEDDED_ROOT));
+        let roots: Vec<_> = self
+            .search_paths
+            .iter()
+            .map(PathBuf::as_path)
+            .chain(embedded)
+            .collect();
+
+        let relative: Vec<_> = match from.and_then(|from| Some((from, from.path.parent()?))) {
+            Some((from, dir)) if from.embedded.is_some() => {
+                let dir = dir.strip_prefix(EMBEDDED_ROOT).unwrap_or(dir);
+                roots.iter().map(|root| root.join(dir).join(name)).collect()
+            }
+            Some((_, dir)) => vec![dir.join(name)],
+            None => Vec::new(),
+        };
+        let candidates = relative
+            .into_iter()
+            .chain(roots.iter().map(|root| root.join(name)));
+
+        for candidate in candidates {
+            let path = normalize(&candidate);
+
+            if let Ok(relative) = path.strip_prefix(EMBEDDED_ROOT) {
+                if let Some((_, src)) = self.embedded.iter().find(|(n, _)| *n == relative) {
+                    return Some(File {
+                        path,
+                        embedded: Some(src),
+                    });
+                }
+            } else if path.is_file() {
+                return Some(File {
+                    path: fs::canonicalize(&path).unwrap_or(path),
+                    embedded: None,
+                });
+            }
+        }
+
+        None
+    }
+
+    fn process(&self, state: &mut State, file: File) -> Result<()> {
+        let path = file.path.as_path();
+        let canonical = file.path.clone();
+
+        if let Some(pos) = state.stack.iter().position(|p| *p == canonical) {
+            let mut chain = String::new();
+            for p in state.stack[pos..].iter().chain([&canonical]) {
+                if !chain.is_empty() {
+                    chain.push_str(" -> ");
+                }
+                chain.push_str(&p.display().to_string());
+            }
+            bail!("include cycle: {chain}");
+        }
+
+        if !state.included.insert(canonical.clone()) {
+            return Ok(());
+        }
+
+        let src = match file.embedded {
+            Some(src) => Cow::Borrowed(src),
+            None => fs::read_to_string(path)
+                .map_err(|err| anyhow!("could not read {}: {err}", path.display()))?
+                .into(),
+        };
+
+        state.stack.push(canonical);
+        let file_index = state.out.line_map.files.len();
+        state.out.line_map.files.push(path.to_path_buf());
+
+        let mut conditionals: Vec<Conditional> = vec![];
+        let active = |conditionals: &[Conditional]| conditionals.last().is_none_or(|c| c.active);
+
+        for (i, line) in src.lines().enumerate() {
+            let line_no = i + 1;
+            let at = || format!("{}:{line_no}", path.display());
+
+            let Some(directive) = line.trim_start().strip_prefix("//!") else {
+                if active(&conditionals) {
+                    state.out.source.push_str(line);
+                    state.out.source.push('\n');
+                    state.out.line_map.lines.push((file_index, line_no));
+                }
+                continue;
+            };
+
+            let (directive, arg) = directive
+                .trim()
+                .split_once(char::is_whitespace)
+                .map_or((directive.trim(), ""), |(d, a)| (d, a.trim()));
+
+            match directive {
+                "ifdef" | "ifndef" => {
+                    if arg.is_empty() {
+                        bail!("{}: //!{directive} without a name", at());
+                    }
+                    let parent_active = active(&conditionals);
+                    let defined = state.defines.contains(arg);
+                    conditionals.push(Conditional {
+                        line: line_no,
+                        active: parent_active && (defined == (directive == "ifdef")),
+                        parent_active,
+                        seen_else: false,
+                    });
+                }
+                "else" => {
+                    let Some(c) = conditionals.last_mut() else {
+                        bail!("{}: //!else without //!ifdef", at());
+                    };
+                    if c.seen_else {
+                        bail!("{}: duplicate //!else", at());
+                    }
+                    c.seen_else = true;
+                    c.active = c.parent_active && !c.active;
+                }
+                "endif" => {
+                    if conditionals.pop().is_none() {
+                        bail!("{}: //!endif without //!ifdef", at());
+                    }
+                }
+                _ if !active(&conditionals) => {}
+                "define" => {
+                    state.defines.insert(arg.to_string());
+                }
+                "undef" => {
+                    state.defines.remove(arg);
+                }
+                "include" => {
+                    let mut split = arg.split('"');
+                    let _before = split.next();
+                    let mut any = false;
+
+                    while let Some(name) = split.next() {
+                        let include = self
+                            .resolve(Some(&file), name)
+                            .ok_or_else(|| anyhow!("{}: could not find \"{name}\"", at()))?;
+                        self.process(state, include)?;
+                        any = true;
+
+                        if split.next().is_none() {
+                            bail!("{}: unexpected end of line", at());
+                        }
+                    }
+
+                    if !any {
+                        bail!("{}: //!include without a path", at());
+                    }
+                }
+                _ => {
+                    tracing::warn!("{}: unknown directive //!{directive}", at());
+                }
+            }
+        }
+
+        if let Some(c) = conditionals.last() {
+            bail!("{}:{}: unterminated conditional", path.display(), c.line);
+        }
+
+        state.stack.pop();
+        Ok(())
+    }
+}
+
+impl LineMap {
+    /// the original file and line of a 1-based preprocessed line
+    pub fn lookup(&self, line: usize) -> Option<(&Path, usize)> {
+        let (file, line) = *self.lines.get(line.checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+
+    /// rewrite every `<name>:line:col` location in a compile diagnostic
+    /// to point to the original file
+    pub fn rewrite(&self, diagnostic: &str, names: &[&str]) -> String {
+        let mut out = String::with_capacity(diagnostic.len());
+        let mut rest = diagnostic;
+
+        'outer: while !rest.is_empty() {
+            // only match whole names, not the end of a longer path
+            let boundary = out
+                .chars()
+                .next_back()
+                .is_none_or(|c| c.is_whitespace() || "([<'\"".contains(c));
+
+            for name in names.iter().filter(|_| boundary) {
+                let Some(tail) = rest.strip_prefix(name).and_then(|t| t.strip_prefix(':')) else {
+                    continue;
+                };
+
+                let digits = tail.bytes().take_while(u8::is_ascii_digit).count();
+                let Some((file, line)) = tail[..digits]
+                    .parse()
+                    .ok()
+                    .and_then(|line| self.lookup(line))
+                else {
+                    continue;
+                };
+
+                _ = write!(out, "{}:{line}", file.display());
+                rest = &tail[digits..];
+                continue 'outer;
+            }
+
+            let mut chars = rest.chars();
+            out.extend(chars.next());
+            rest = chars.as_str();
+        }
+
+        out
+    }
+}
+
+/// lexically resolve `.` and `..`
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !out.pop() {
+                    out.push(component);
+                }
+            }
+            _ => out.push(component),
+        }
+    }
+    out
+}
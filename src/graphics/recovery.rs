@@ -0,0 +1,66 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use wgpu::{Device, DeviceLostReason, Error};
+
+//
+
+/// flags set from the wgpu callbacks when the device has to be recreated
+#[derive(Debug, Clone, Default)]
+pub struct DeviceHealth {
+    lost: Arc<AtomicBool>,
+    out_of_memory: Arc<AtomicBool>,
+    /// a surface could not be recreated, the device may still be readable
+    failed: Arc<AtomicBool>,
+}
+
+//
+
+impl DeviceHealth {
+    /// install the device lost and error callbacks of `device`
+    pub fn watch(device: &Device) -> Self {
+        let health = Self::default();
+
+        let lost = health.lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            if matches!(reason, DeviceLostReason::Destroyed) {
+                return;
+            }
+            tracing::error!("GPU device lost: {message}");
+            lost.store(true, Ordering::Release);
+        });
+
+        let out_of_memory = health.out_of_memory.clone();
+        device.on_uncaptured_error(Box::new(move |err| match err {
+            Error::OutOfMemory { .. } => {
+                tracing::error!("GPU out of memory: {err}");
+                out_of_memory.store(true, Ordering::Release);
+            }
+            // same as the default handler
+            err => panic!("wgpu error: {err}"),
+        }));
+
+        health
+    }
+
+    /// the device is gone, nothing can be read back from it
+    pub fn lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    pub fn set_out_of_memory(&self) {
+        self.out_of_memory.store(true, Ordering::Release);
+    }
+
+    pub fn set_failed(&self) {
+        self.failed.store(true, Ordering::Release);
+    }
+
+    pub fn needs_recovery(&self) -> bool {
+        self.lost()
+            || self.out_of_memory.load(Ordering::Acquire)
+            || self.failed.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,104 @@
+use glam::Vec2;
+
+/// a window position and size on the desktop, in pixels
+pub type Rect = ((i32, i32), (u32, u32));
+
+//
+
+/// the part of the simulation domain a view shows, in target uv,
+/// y-up like the target, the particles and the blit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub offset: Vec2,
+    pub scale: Vec2,
+}
+
+//
+
+impl Region {
+    pub const FULL: Self = Self {
+        offset: Vec2::ZERO,
+        scale: Vec2::ONE,
+    };
+
+    /// the regions of views placed at `rects` (position and size),
+    /// and the size of the domain covering all of them, at least `min`
+    pub fn layout(rects: &[Rect], min: (u32, u32)) -> ((u32, u32), Vec<Self>) {
+        let min_x = rects.iter().map(|((x, _), _)| *x).min().unwrap_or(0);
+        let min_y = rects.iter().map(|((_, y), _)| *y).min().unwrap_or(0);
+        let max_x = rects.iter().map(|((x, _), (w, _))| *x + *w as i32).max();
+        let max_y = rects.iter().map(|((_, y), (_, h))| *y + *h as i32).max();
+        let max_y = max_y.unwrap_or(1);
+
+        let width = ((max_x.unwrap_or(1) - min_x).max(1) as u32).max(min.0);
+        let height = ((max_y - min_y).max(1) as u32).max(min.1);
+        let domain = Vec2::new(width as f32, height as f32);
+
+        // the desktop is y-down, the offset is from the bottom of the domain
+        let regions = rects
+            .iter()
+            .map(|((x, y), (w, h))| Self {
+                offset: Vec2::new((x - min_x) as f32, (max_y - y - *h as i32) as f32) / domain,
+                scale: Vec2::new(*w as f32, *h as f32) / domain,
+            })
+            .collect();
+
+        ((width, height), regions)
+    }
+
+    /// the target uv the blit samples at `uv` of the view, y-up, mirrors `blit.wgsl`
+    pub fn to_target(&self, uv: Vec2) -> Vec2 {
+        self.offset + uv * self.scale
+    }
+
+    /// y-down pixels of a view with `size` to the uv of the whole domain,
+    /// y-down like the cursor of the update shader
+    pub fn to_domain(&self, pixel: Vec2, size: Vec2) -> Vec2 {
+        let uv = Vec2::new(pixel.x / size.x, 1.0 - pixel.y / size.y);
+        let target = self.to_target(uv);
+        Vec2::new(target.x, 1.0 - target.y)
+    }
+}
+
+/// where to open `count` windows, the first one at `first`:
+/// each extra one on the next monitor of `monitors` without a window,
+/// filling it with `fill` (fullscreen), then right of every window so far,
+/// returns the rects and the monitors they are on
+pub fn place(
+    first: Rect,
+    monitors: &[Rect],
+    count: usize,
+    fill: bool,
+) -> Vec<(Rect, Option<usize>)> {
+    let ((x, y), (w, h)) = first;
+    let center = (x + w as i32 / 2, y + h as i32 / 2);
+    let first_monitor = monitors.iter().position(|((mx, my), (mw, mh))| {
+        (*mx..mx + *mw as i32).contains(&center.0) && (*my..my + *mh as i32).contains(&center.1)
+    });
+
+    let mut free = (0..monitors.len()).filter(|i| Some(*i) != first_monitor);
+    let mut placed = vec![(first, first_monitor)];
+    for _ in 1..count {
+        let next = match free.next() {
+            Some(i) => {
+                let (position, size) = monitors[i];
+                let size = if fill {
+                    size
+                } else {
+                    (w.min(size.0), h.min(size.1))
+                };
+                ((position, size), Some(i))
+            }
+            None => {
+                let right = placed
+                    .iter()
+                    .map(|(((x, _), (w, _)), _)| x + *w as i32)
+                    .max()
+                    .unwrap_or(x);
+                (((right, y), (w, h)), None)
+            }
+        };
+        placed.push(next);
+    }
+    placed
+}
@@ -0,0 +1,550 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use glam::Vec2;
+use wgpu::*;
+
+use super::{
+    dispatch::WorkgroupSizes,
+    flow::FlowConfig,
+    params::{ParamMode, Params},
+    post::Post,
+    preprocess::Preprocessor,
+    region::Region,
+    resample::Resampler,
+    shaders::{create_module, load_module, preprocessor, validated},
+    sim::{SimContext, Simulation},
+    target::TargetMode,
+    timing::{GpuTimer, TimedPass},
+    variant::{SimPipelines, SimVariant, VariantCache},
+};
+
+//
+
+/// the pipelines drawing simulations into scene textures,
+/// shared by the windows of `Graphics` and by [`super::flow::Flow`]
+pub struct Renderer {
+    pub ctx: SimContext,
+
+    blit_params: Params<BlitPush>,
+    blit_layout: PipelineLayout,
+    blit_pipeline: RenderPipeline,
+
+    shadow_layout: PipelineLayout,
+    update_params: Params<UpdatePush>,
+    update_layout: PipelineLayout,
+
+    /// the last variant that compiled, always in the cache
+    sim_variant: SimVariant,
+    sim_variants: VariantCache,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub(super) struct UpdatePush {
+    pub cursor: Vec2,
+    pub time: f32,
+    pub _pad: u32,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub(super) struct BlitPush {
+    /// the region of the target shown by the view
+    pub offset: Vec2,
+    pub scale: Vec2,
+    pub flags: u32,
+    pub _pad: u32,
+}
+
+//
+
+impl Renderer {
+    /// `sims` and `views` are the most simulations and views drawn per submit,
+    /// `adapter` is only asked for the features of `device`
+    pub fn new(
+        adapter: &Adapter,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        config: &FlowConfig,
+        sims: u32,
+        views: u32,
+    ) -> Result<Self> {
+        let limits = device.limits();
+
+        // without it the accumulation texture is sampled with the nearest texel
+        let filterable = device.features().contains(Features::FLOAT32_FILTERABLE);
+
+        let workgroups = WorkgroupSizes::new(&limits);
+        tracing::debug!("{workgroups:?}");
+
+        let param_mode = ParamMode::new(&device, config.push_constants);
+        tracing::info!("pass parameters use {param_mode:?}");
+
+        let target_mode = TargetMode::new(adapter, &device, config.storage_textures);
+        tracing::info!("the accumulation image is a {target_mode:?}");
+
+        let update_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: target_mode.binding_type(),
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let update_params = Params::new(&device, param_mode, ShaderStages::COMPUTE, 1, sims);
+        let update_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &update_params.bind_group_layouts(&[&update_bind_group_layout]),
+            push_constant_ranges: update_params.push_constant_ranges(),
+        });
+
+        let shadow_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: target_mode.binding_type(),
+                    count: None,
+                }],
+            });
+
+        let shadow_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&shadow_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shaders = [param_mode.define(), target_mode.define()]
+            .into_iter()
+            .flatten()
+            .fold(
+                preprocessor(config.shader_dir.as_deref()),
+                |shaders, define| shaders.define(define),
+            );
+        let sim_variant = SimVariant::default();
+        let mut sim_variants = VariantCache::default();
+        sim_variants.reset(
+            sim_variant,
+            Self::create_sim_pipelines(
+                &device,
+                &shaders,
+                workgroups,
+                sim_variant,
+                &update_layout,
+                &shadow_layout,
+            )?,
+        );
+
+        /* let update_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &update_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: &points,
+                    offset: 0,
+                    size: None,
+                }),
+            }],
+        }); */
+
+        let blit_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(if filterable {
+                        SamplerBindingType::Filtering
+                    } else {
+                        SamplerBindingType::NonFiltering
+                    }),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let blit_params = Params::new(&device, param_mode, ShaderStages::VERTEX_FRAGMENT, 1, views);
+        let blit_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            // bind_group_layouts: &[],
+            bind_group_layouts: &blit_params.bind_group_layouts(&[&blit_bind_group_layout]),
+            push_constant_ranges: blit_params.push_constant_ranges(),
+        });
+
+        let blit_pipeline = Self::create_blit_pipeline(&device, &shaders, &blit_layout)?;
+
+        let filter = if filterable {
+            FilterMode::Linear
+        } else {
+            FilterMode::Nearest
+        };
+        let blit_sampler = device.create_sampler(&SamplerDescriptor {
+            label: None,
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            // the accumulation texture is resampled to the window size
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: FilterMode::Nearest,
+            compare: None,
+            anisotropy_clamp: 1,
+
+            ..<_>::default()
+        });
+
+        let resampler = Resampler::new(&device, &shaders, param_mode, TextureFormat::R32Float)?;
+
+        let ctx = SimContext {
+            device,
+            queue,
+            limits,
+            workgroups,
+            param_mode,
+            target_mode,
+            shaders,
+
+            render_scale: config.render_scale,
+            resize_mode: config.resize_mode,
+            exposure: config.exposure,
+
+            blit_sampler,
+            blit_bind_group_layout,
+            shadow_bind_group_layout,
+            update_bind_group_layout,
+            resampler,
+        };
+
+        Ok(Self {
+            ctx,
+
+            blit_params,
+            blit_layout,
+            blit_pipeline,
+
+            shadow_layout,
+            update_params,
+            update_layout,
+
+            sim_variant,
+            sim_variants,
+        })
+    }
+
+    fn create_sim_pipelines(
+        device: &Device,
+        shaders: &Preprocessor,
+        workgroups: WorkgroupSizes,
+        variant: SimVariant,
+        update_layout: &PipelineLayout,
+        shadow_layout: &PipelineLayout,
+    ) -> Result<SimPipelines> {
+        validated(device, || {
+            let shaders = variant
+                .defines()
+                .fold(shaders.clone(), |shaders, define| shaders.define(define));
+            let mut shader = shaders.load("shader.wgsl")?;
+            workgroups.apply(&mut shader.source);
+            let module = create_module(device, "shader.wgsl", &shader)?;
+
+            let update_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("update pipeline"),
+                layout: Some(update_layout),
+                module: &module,
+                entry_point: "cs_main_update",
+            });
+
+            let shadow_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some("shadow pipeline"),
+                layout: Some(shadow_layout),
+                module: &module,
+                entry_point: "cs_main_shadow",
+            });
+
+            Ok(SimPipelines {
+                update: update_pipeline,
+                shadow: shadow_pipeline,
+            })
+        })
+    }
+
+    fn create_blit_pipeline(
+        device: &Device,
+        shaders: &Preprocessor,
+        blit_layout: &PipelineLayout,
+    ) -> Result<RenderPipeline> {
+        validated(device, || {
+            let module = load_module(device, shaders, "blit.wgsl")?;
+
+            Ok(device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("blit pipeline"),
+                layout: Some(blit_layout),
+                vertex: VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: PrimitiveState {
+                    topology: PrimitiveTopology::TriangleStrip,
+                    strip_index_format: None,
+                    front_face: FrontFace::Ccw,
+                    cull_mode: None,
+                    unclipped_depth: false,
+                    polygon_mode: PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: <_>::default(),
+                fragment: Some(FragmentState {
+                    module: &module,
+                    entry_point: "fs_main",
+                    targets: &[Some(ColorTargetState {
+                        format: Post::FORMAT,
+                        blend: Some(BlendState::ALPHA_BLENDING),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            }))
+        })
+    }
+
+    /// the post chain for a view drawing into `output_format`
+    pub fn create_post(
+        &self,
+        config: &FlowConfig,
+        output_format: TextureFormat,
+        alpha_mode: CompositeAlphaMode,
+        size: (u32, u32),
+    ) -> Result<Post> {
+        Post::new(
+            &self.ctx.device,
+            &self.ctx.shaders,
+            self.ctx.param_mode,
+            config.post.clone(),
+            output_format,
+            alpha_mode,
+            size,
+        )
+    }
+
+    /// rebuild every pipeline from the shader files, including the ones of
+    /// `sims` and `posts`, the pipelines that fail to compile keep their last good version
+    pub fn reload_shaders<'a>(
+        &mut self,
+        sims: &mut [Simulation],
+        posts: impl IntoIterator<Item = &'a mut Post>,
+    ) {
+        tracing::info!("reloading shaders");
+
+        let device = &self.ctx.device;
+        let shaders = &self.ctx.shaders;
+        let results = [
+            ("shader.wgsl", {
+                // the other variants are rebuilt when they are used again
+                Self::create_sim_pipelines(
+                    device,
+                    shaders,
+                    self.ctx.workgroups,
+                    self.sim_variant,
+                    &self.update_layout,
+                    &self.shadow_layout,
+                )
+                .map(|pipelines| self.sim_variants.reset(self.sim_variant, pipelines))
+            }),
+            (
+                "blit.wgsl",
+                Self::create_blit_pipeline(device, shaders, &self.blit_layout)
+                    .map(|blit| self.blit_pipeline = blit),
+            ),
+            (
+                "exposure.wgsl",
+                sims.iter_mut()
+                    .try_for_each(|sim| sim.reload(device, shaders)),
+            ),
+            (
+                "post.wgsl",
+                posts
+                    .into_iter()
+                    .try_for_each(|post| post.reload(device, shaders)),
+            ),
+            ("resample.wgsl", self.ctx.resampler.reload(device, shaders)),
+        ];
+
+        for (shader, result) in results {
+            if let Err(err) = result {
+                tracing::error!(
+                    "Failed to reload {shader}, keeping the last good pipeline:\n{err}"
+                );
+            }
+        }
+    }
+
+    /// switch to the pipelines of the runtime `flags`, compiling them on first use,
+    /// keeps the current variant if it fails to compile
+    pub fn set_flags(&mut self, flags: u32) {
+        let variant = SimVariant::from_flags(flags);
+        if variant == self.sim_variant {
+            return;
+        }
+
+        let usable = self.sim_variants.get_or_create(variant, || {
+            tracing::debug!(
+                "compiling the shader variant {:?}",
+                variant.defines().collect::<Vec<_>>()
+            );
+            Self::create_sim_pipelines(
+                &self.ctx.device,
+                &self.ctx.shaders,
+                self.ctx.workgroups,
+                variant,
+                &self.update_layout,
+                &self.shadow_layout,
+            )
+        });
+
+        if usable {
+            self.sim_variant = variant;
+        }
+    }
+
+    /// the shadow and update passes of every simulation,
+    /// drawing into their accumulation textures, `timer` times the first one
+    pub fn encode_sims(
+        &self,
+        encoder: &mut CommandEncoder,
+        sims: &[Simulation],
+        time: f32,
+        timer: Option<&GpuTimer>,
+    ) {
+        let pipelines = self
+            .sim_variants
+            .get(self.sim_variant)
+            .expect("the active shader variant is always compiled");
+
+        for (i, sim) in sims.iter().enumerate() {
+            let timer = timer.filter(|_| i == 0);
+
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("shadow pass"),
+                timestamp_writes: timer.map(|t| t.compute(TimedPass::Shadow)),
+            });
+
+            pass.set_pipeline(&pipelines.shadow);
+            pass.set_bind_group(0, sim.shadow_bind_group(), &[]);
+            let (x, y) = self.ctx.workgroups.shadow_dispatch(sim.target_size());
+            pass.dispatch_workgroups(x, y, 1);
+
+            drop(pass);
+
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("update pass"),
+                timestamp_writes: timer.map(|t| t.compute(TimedPass::Update)),
+            });
+
+            pass.set_pipeline(&pipelines.update);
+
+            let push = UpdatePush {
+                cursor: sim.cursor,
+                time,
+                _pad: 0,
+            };
+
+            self.update_params.set(&self.ctx.queue, &mut pass, &push);
+            pass.set_bind_group(0, sim.update_bind_group(), &[]);
+            let (x, y) = self
+                .ctx
+                .workgroups
+                .update_dispatch(&self.ctx.limits, sim.points_len());
+            pass.dispatch_workgroups(x, y, 1);
+
+            drop(pass);
+
+            sim.resolve(encoder);
+        }
+    }
+
+    /// draw `region` of the exposed accumulation texture into `scene`,
+    /// a [`Post::scene_view`]
+    pub fn encode_blit(
+        &self,
+        encoder: &mut CommandEncoder,
+        sim: &Simulation,
+        region: Region,
+        scene: &TextureView,
+        timer: Option<&GpuTimer>,
+    ) {
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("blit pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: scene,
+                resolve_target: None,
+                /* ops: Operations {
+                    load: LoadOp::Load, // no clear
+                    store: StoreOp::Store,
+                }, */
+                ops: Operations {
+                    load: LoadOp::Clear(Color {
+                        r: 0.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 0.0,
+                    }),
+                    store: StoreOp::Store,
+                },
+            })],
+            timestamp_writes: timer.map(|t| t.render(TimedPass::Blit)),
+            ..<_>::default()
+        });
+
+        pass.set_pipeline(&self.blit_pipeline);
+        let push = BlitPush {
+            offset: region.offset,
+            scale: region.scale,
+            flags: 0,
+            _pad: 0,
+        };
+        self.blit_params.set(&self.ctx.queue, &mut pass, &push);
+        pass.set_bind_group(0, sim.blit_bind_group(), &[]);
+        pass.draw(0..4, 0..1);
+    }
+}
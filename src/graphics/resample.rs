@@ -0,0 +1,181 @@
+use anyhow::Result;
+use bytemuck::{Pod, Zeroable};
+use glam::Vec2;
+use wgpu::*;
+
+use crate::config::ResizeMode;
+
+use super::{
+    params::{ParamMode, Params},
+    preprocess::Preprocessor,
+    shaders::{load_module, validated},
+};
+
+//
+
+/// copies the contents of one accumulation texture
+/// into another one of a different size
+pub struct Resampler {
+    format: TextureFormat,
+    bind_group_layout: BindGroupLayout,
+    params: Params<ResamplePush>,
+    layout: PipelineLayout,
+    pipeline: RenderPipeline,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub(super) struct ResamplePush {
+    pub scale: Vec2,
+    pub offset: Vec2,
+}
+
+//
+
+impl Resampler {
+    pub fn new(
+        device: &Device,
+        shaders: &Preprocessor,
+        param_mode: ParamMode,
+        format: TextureFormat,
+    ) -> Result<Self> {
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let params = Params::new(device, param_mode, ShaderStages::FRAGMENT, 1, 1);
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &params.bind_group_layouts(&[&bind_group_layout]),
+            push_constant_ranges: params.push_constant_ranges(),
+        });
+
+        let pipeline = Self::create_pipeline(device, shaders, &layout, format)?;
+
+        Ok(Self {
+            format,
+            bind_group_layout,
+            params,
+            layout,
+            pipeline,
+        })
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        shaders: &Preprocessor,
+        layout: &PipelineLayout,
+        format: TextureFormat,
+    ) -> Result<RenderPipeline> {
+        validated(device, || {
+            let module = load_module(device, shaders, "resample.wgsl")?;
+
+            Ok(device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("resample pipeline"),
+                layout: Some(layout),
+                vertex: VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: PrimitiveState {
+                    topology: PrimitiveTopology::TriangleStrip,
+                    ..<_>::default()
+                },
+                depth_stencil: None,
+                multisample: <_>::default(),
+                fragment: Some(FragmentState {
+                    module: &module,
+                    entry_point: "fs_main",
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            }))
+        })
+    }
+
+    /// recompile the shader, keeping the old pipeline on failure
+    pub fn reload(&mut self, device: &Device, shaders: &Preprocessor) -> Result<()> {
+        self.pipeline = Self::create_pipeline(device, shaders, &self.layout, self.format)?;
+        Ok(())
+    }
+
+    /// draw `src` into `dst`, fitting it with `mode`
+    pub fn encode(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        mode: ResizeMode,
+        src: &Texture,
+        dst: &Texture,
+    ) {
+        if mode == ResizeMode::Clear {
+            return;
+        }
+
+        let src_size = Vec2::new(src.width() as f32, src.height() as f32);
+        let dst_size = Vec2::new(dst.width() as f32, dst.height() as f32);
+
+        // the part of the source visible in the destination, in source uv
+        let span = match mode {
+            ResizeMode::Clear | ResizeMode::Stretch => Vec2::ONE,
+            ResizeMode::Crop => {
+                let scale = (dst_size / src_size).max_element();
+                dst_size / (src_size * scale)
+            }
+            ResizeMode::Letterbox => {
+                let scale = (dst_size / src_size).min_element();
+                dst_size / (src_size * scale)
+            }
+        };
+        let push = ResamplePush {
+            scale: span,
+            offset: (Vec2::ONE - span) * 0.5,
+        };
+
+        let src_view = src.create_view(&<_>::default());
+        let dst_view = dst.create_view(&<_>::default());
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("resample bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&src_view),
+            }],
+        });
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("resample pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &dst_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::TRANSPARENT),
+                    store: StoreOp::Store,
+                },
+            })],
+            ..<_>::default()
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        self.params.set(queue, &mut pass, &push);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..4, 0..1);
+    }
+}
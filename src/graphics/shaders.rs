@@ -0,0 +1,73 @@
+use std::{borrow::Cow, path::Path};
+
+use anyhow::{anyhow, Result};
+use wgpu::{Device, ErrorFilter, ShaderModule, ShaderModuleDescriptor, ShaderSource};
+
+use super::preprocess::{Preprocessed, Preprocessor};
+
+//
+
+macro_rules! embed {
+    ($name:literal) => {
+        ($name, include_str!(concat!("../../asset/", $name)))
+    };
+}
+
+/// every shader in `./asset`, compiled into the binary
+pub static EMBEDDED: &[(&str, &str)] = &[
+    embed!("blit.wgsl"),
+    embed!("exposure.wgsl"),
+    embed!("noise.wgsl"),
+    embed!("post.wgsl"),
+    embed!("resample.wgsl"),
+    embed!("shader.wgsl"),
+];
+
+//
+
+/// runs `f` inside a validation error scope,
+/// shader compile and pipeline errors are returned instead of panicking
+pub fn validated<T>(device: &Device, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    device.push_error_scope(ErrorFilter::Validation);
+    let result = f();
+    let error = pollster::block_on(device.pop_error_scope());
+
+    let value = result?;
+    match error {
+        Some(err) => Err(anyhow!("{err}")),
+        None => Ok(value),
+    }
+}
+
+/// the embedded shaders, or the shaders in `shader_dir` if set
+///
+/// includes missing from `shader_dir` fall back to the embedded ones
+pub fn preprocessor(shader_dir: Option<&Path>) -> Preprocessor {
+    let preprocessor = Preprocessor::new().embedded(EMBEDDED);
+    match shader_dir {
+        Some(dir) => preprocessor.search_path(dir),
+        None => preprocessor,
+    }
+}
+
+/// compile a preprocessed shader,
+/// the compile errors point to the original files
+pub fn create_module(device: &Device, name: &str, shader: &Preprocessed) -> Result<ShaderModule> {
+    device.push_error_scope(ErrorFilter::Validation);
+    let module = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(name),
+        source: ShaderSource::Wgsl(Cow::Borrowed(&shader.source)),
+    });
+
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(err) => Err(anyhow!(shader
+            .line_map
+            .rewrite(&err.to_string(), &["wgsl", name]))),
+        None => Ok(module),
+    }
+}
+
+/// preprocess and compile a shader
+pub fn load_module(device: &Device, shaders: &Preprocessor, name: &str) -> Result<ShaderModule> {
+    create_module(device, name, &shaders.load(name)?)
+}
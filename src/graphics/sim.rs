@@ -0,0 +1,442 @@
+use std::{mem::size_of, sync::Arc};
+
+use anyhow::{bail, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::Vec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    *,
+};
+
+use crate::config::{ExposureSettings, ResizeMode};
+
+use super::{
+    dispatch::WorkgroupSizes,
+    exposure::Exposure,
+    params::ParamMode,
+    preprocess::Preprocessor,
+    resample::Resampler,
+    snapshot::{self, Snapshot},
+    target::TargetBuffer,
+    target::TargetMode,
+};
+
+//
+
+/// the device objects shared by every [`Simulation`]
+pub struct SimContext {
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    pub limits: Limits,
+    pub workgroups: WorkgroupSizes,
+    pub param_mode: ParamMode,
+    pub target_mode: TargetMode,
+    pub shaders: Preprocessor,
+
+    pub render_scale: f32,
+    pub resize_mode: ResizeMode,
+    pub exposure: ExposureSettings,
+
+    pub blit_sampler: Sampler,
+    pub blit_bind_group_layout: BindGroupLayout,
+    pub shadow_bind_group_layout: BindGroupLayout,
+    pub update_bind_group_layout: BindGroupLayout,
+    pub resampler: Resampler,
+}
+
+/// the particles and the accumulation image they draw into
+pub struct Simulation {
+    points: Buffer,
+    points_len: u32,
+
+    target: Texture,
+    target_buffer: Option<TargetBuffer>,
+    /// the domain size before `render_scale`
+    size: (u32, u32),
+    target_size: (u32, u32),
+    exposure: Exposure,
+
+    blit_bind_group: BindGroup,
+    shadow_bind_group: BindGroup,
+    update_bind_group: BindGroup,
+
+    /// the initial particles are generated from this
+    seed: u64,
+    /// in target uv, pulls the particles around
+    pub cursor: Vec2,
+}
+
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct Instance {
+    pos: Vec2,
+    vel: Vec2,
+}
+
+//
+
+impl Simulation {
+    const POINTS: u32 = 100_000;
+
+    /// `size` is the domain size in window pixels,
+    /// the particles start at random positions from `seed`
+    pub fn new(ctx: &SimContext, size: (u32, u32), seed: u64) -> Result<Self> {
+        let device = &ctx.device;
+
+        let points_len = Self::POINTS;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let points: Vec<_> = (0..points_len)
+            .map(|_| Instance {
+                pos: Vec2::new(rng.gen(), rng.gen()) * 4.0 - 2.0,
+                vel: Vec2::new(rng.gen(), rng.gen()) * 0.001 - 0.0005,
+            })
+            .collect();
+        let points = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&points[..]),
+            // copied back and forth by snapshots
+            usage: BufferUsages::VERTEX
+                | BufferUsages::STORAGE
+                | BufferUsages::COPY_SRC
+                | BufferUsages::COPY_DST,
+        });
+
+        let (target, target_size) = Self::create_target(ctx, size);
+        let target_view = target.create_view(&TextureViewDescriptor { ..<_>::default() });
+        let target_buffer = (ctx.target_mode == TargetMode::StorageBuffer)
+            .then(|| TargetBuffer::new(device, &ctx.queue, target_size));
+
+        let exposure = Exposure::new(
+            device,
+            &ctx.shaders,
+            ctx.param_mode,
+            ctx.exposure,
+            &target_view,
+            target_size,
+        )?;
+
+        let (blit_bind_group, shadow_bind_group, update_bind_group) = Self::create_bind_groups(
+            ctx,
+            &target_view,
+            target_buffer.as_ref(),
+            &points,
+            exposure.uniform(),
+        );
+
+        Ok(Self {
+            points,
+            points_len,
+
+            target,
+            target_buffer,
+            size,
+            target_size,
+            exposure,
+
+            blit_bind_group,
+            shadow_bind_group,
+            update_bind_group,
+
+            seed,
+            // off screen
+            cursor: Vec2::splat(-1.0),
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn target_size(&self) -> (u32, u32) {
+        self.target_size
+    }
+
+    pub fn points_len(&self) -> u32 {
+        self.points_len
+    }
+
+    pub fn exposure(&self) -> &Exposure {
+        &self.exposure
+    }
+
+    pub fn blit_bind_group(&self) -> &BindGroup {
+        &self.blit_bind_group
+    }
+
+    pub fn shadow_bind_group(&self) -> &BindGroup {
+        &self.shadow_bind_group
+    }
+
+    pub fn update_bind_group(&self) -> &BindGroup {
+        &self.update_bind_group
+    }
+
+    /// recompile the exposure shader, keeping the old pipelines on failure
+    pub fn reload(&mut self, device: &Device, shaders: &Preprocessor) -> Result<()> {
+        self.exposure.reload(device, shaders)
+    }
+
+    /// copy the particles and the accumulation image back to the CPU
+    pub fn snapshot(&self, ctx: &SimContext) -> Result<Snapshot> {
+        let (points, target) =
+            snapshot::read_back(&ctx.device, &ctx.queue, &self.points, &self.target)?;
+
+        Ok(Snapshot {
+            seed: self.seed,
+            cursor: self.cursor,
+            points,
+            target_size: self.target_size,
+            target,
+        })
+    }
+
+    /// upload a [`Snapshot`] again, an image of another size is
+    /// resampled with `resize_mode` (`Clear` stretches it)
+    pub fn restore(&mut self, ctx: &SimContext, snapshot: &Snapshot) -> Result<()> {
+        let points_size = self.points_len as usize * size_of::<Instance>();
+        if snapshot.points.len() != points_size {
+            bail!(
+                "the snapshot has {} particles, expected {}",
+                snapshot.points.len() / size_of::<Instance>(),
+                self.points_len
+            );
+        }
+        let (width, height) = snapshot.target_size;
+        if width == 0 || height == 0 || snapshot.target.len() != width as usize * height as usize {
+            bail!("the snapshot image doesn't match its size {width}x{height}");
+        }
+
+        ctx.queue.write_buffer(&self.points, 0, &snapshot.points);
+
+        let upload = |texture: &Texture| {
+            ctx.queue.write_texture(
+                texture.as_image_copy(),
+                bytemuck::cast_slice(&snapshot.target),
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(width * 4),
+                    rows_per_image: None,
+                },
+                texture.size(),
+            );
+        };
+
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { ..<_>::default() });
+
+        if snapshot.target_size == self.target_size {
+            upload(&self.target);
+        } else {
+            tracing::info!(
+                "resampling the {width}x{height} snapshot to {}x{}",
+                self.target_size.0,
+                self.target_size.1
+            );
+
+            let texture = ctx.device.create_texture(&TextureDescriptor {
+                label: Some("snapshot upload"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R32Float,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            upload(&texture);
+
+            let mode = match ctx.resize_mode {
+                ResizeMode::Clear => ResizeMode::Stretch,
+                mode => mode,
+            };
+            ctx.resampler.encode(
+                &ctx.device,
+                &ctx.queue,
+                &mut encoder,
+                mode,
+                &texture,
+                &self.target,
+            );
+        }
+
+        // the compute passes draw into the buffer
+        if let Some(buffer) = self.target_buffer.as_ref() {
+            buffer.load(&mut encoder, &self.target);
+        }
+        ctx.queue.submit([encoder.finish()]);
+
+        self.seed = snapshot.seed;
+        self.cursor = snapshot.cursor;
+        Ok(())
+    }
+
+    /// copy the storage buffer target into the texture after the compute passes
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        if let Some(buffer) = self.target_buffer.as_ref() {
+            buffer.resolve(encoder, &self.target);
+        }
+    }
+
+    /// the accumulation texture, returns the texture and its scaled and clamped size
+    fn create_target(ctx: &SimContext, (width, height): (u32, u32)) -> (Texture, (u32, u32)) {
+        let render_scale = ctx.render_scale;
+        let mut scale = render_scale.max(0.01);
+
+        // keep the aspect ratio when the scaled size doesn't fit
+        let max = ctx.limits.max_texture_dimension_2d as f32;
+        let largest = width.max(height) as f32 * scale;
+        if largest > max {
+            tracing::warn!(
+                "render scale {render_scale} exceeds the max texture size {max}, clamping"
+            );
+            scale *= max / largest;
+        }
+
+        // the storage buffer fallback holds the whole image in one binding
+        let max_texels = ctx.target_mode.max_texels(&ctx.limits) as f32;
+        let texels = width as f32 * height as f32 * scale * scale;
+        if texels > max_texels {
+            tracing::warn!(
+                "render scale {render_scale} exceeds the max storage buffer size, clamping"
+            );
+            scale *= (max_texels / texels).sqrt();
+        }
+
+        let width = ((width as f32 * scale).round() as u32).clamp(1, max as u32);
+        let height = ((height as f32 * scale).round() as u32).clamp(1, max as u32);
+
+        let target = ctx.device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Float,
+            usage: ctx.target_mode.texture_usages(),
+            view_formats: &[],
+        });
+
+        (target, (width, height))
+    }
+
+    fn create_bind_groups(
+        ctx: &SimContext,
+        target_view: &TextureView,
+        target_buffer: Option<&TargetBuffer>,
+        points: &Buffer,
+        exposure: &Buffer,
+    ) -> (BindGroup, BindGroup, BindGroup) {
+        let device = &ctx.device;
+
+        // the compute passes use the buffer if there is one
+        let sim_target = || {
+            target_buffer.map_or(
+                BindingResource::TextureView(target_view),
+                TargetBuffer::binding,
+            )
+        };
+
+        let blit_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("blit bind group"),
+            layout: &ctx.blit_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(target_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&ctx.blit_sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: exposure.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shadow_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("shadow bind group"),
+            layout: &ctx.shadow_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: sim_target(),
+            }],
+        });
+
+        let update_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("update bind group"),
+            layout: &ctx.update_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: sim_target(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: points,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+            ],
+        });
+
+        (blit_bind_group, shadow_bind_group, update_bind_group)
+    }
+
+    /// change the domain size, carrying the image over with `resize_mode`
+    pub fn resized(&mut self, ctx: &SimContext, size: (u32, u32)) {
+        let (target, target_size) = Self::create_target(ctx, size);
+        let target_view = target.create_view(&TextureViewDescriptor { ..<_>::default() });
+        let target_buffer = (ctx.target_mode == TargetMode::StorageBuffer)
+            .then(|| TargetBuffer::new(&ctx.device, &ctx.queue, target_size));
+
+        // carry the long exposure image over to the new texture
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { ..<_>::default() });
+        ctx.resampler.encode(
+            &ctx.device,
+            &ctx.queue,
+            &mut encoder,
+            ctx.resize_mode,
+            &self.target,
+            &target,
+        );
+        if let Some(buffer) = target_buffer.as_ref() {
+            buffer.load(&mut encoder, &target);
+        }
+        ctx.queue.submit([encoder.finish()]);
+
+        self.exposure
+            .resized(&ctx.device, &target_view, target_size);
+
+        (
+            self.blit_bind_group,
+            self.shadow_bind_group,
+            self.update_bind_group,
+        ) = Self::create_bind_groups(
+            ctx,
+            &target_view,
+            target_buffer.as_ref(),
+            &self.points,
+            self.exposure.uniform(),
+        );
+        self.target = target;
+        self.target_buffer = target_buffer;
+        self.target_size = target_size;
+        self.size = size;
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::mpsc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use glam::Vec2;
+use wgpu::*;
+
+//
+
+/// the state of one simulation, restored with [`super::sim::Simulation::restore`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// the initial particles were generated from this
+    pub seed: u64,
+    pub cursor: Vec2,
+    /// the raw contents of the particle buffer
+    pub points: Vec<u8>,
+    pub target_size: (u32, u32),
+    /// the accumulation image, rows of `target_size.0` texels
+    pub target: Vec<f32>,
+}
+
+/// a snapshot of every simulation, as saved to disk
+///
+/// the file is little endian:
+/// `FLOWSNAP`, the version, the header fields and each [`Snapshot`] in field order
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotFile {
+    /// seconds simulated, the shaders animate the noise with it
+    pub time: f32,
+    /// the runtime flags, see [`super::variant::SimVariant`]
+    pub flags: u32,
+    pub sims: Vec<Snapshot>,
+}
+
+//
+
+impl SnapshotFile {
+    const MAGIC: &'static [u8; 8] = b"FLOWSNAP";
+    const VERSION: u32 = 1;
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()?;
+
+        tracing::info!("saved a snapshot to {path:?}");
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|err| anyhow!("Failed to open {path:?}: {err}"))?;
+        let snapshot = Self::read(&mut BufReader::new(file))
+            .map_err(|err| anyhow!("Failed to read the snapshot {path:?}: {err}"))?;
+
+        tracing::info!("loaded a snapshot from {path:?}");
+        Ok(snapshot)
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> Result<()> {
+        w.write_all(Self::MAGIC)?;
+        write_u32(w, Self::VERSION)?;
+
+        w.write_all(&self.time.to_le_bytes())?;
+        write_u32(w, self.flags)?;
+        write_u32(w, self.sims.len() as u32)?;
+
+        for sim in self.sims.iter() {
+            w.write_all(&sim.seed.to_le_bytes())?;
+            w.write_all(&sim.cursor.x.to_le_bytes())?;
+            w.write_all(&sim.cursor.y.to_le_bytes())?;
+
+            write_u32(w, sim.points.len() as u32)?;
+            w.write_all(&sim.points)?;
+
+            write_u32(w, sim.target_size.0)?;
+            write_u32(w, sim.target_size.1)?;
+            for texel in sim.target.iter() {
+                w.write_all(&texel.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> Result<Self> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != Self::MAGIC {
+            bail!("not a snapshot file");
+        }
+
+        let version = read_u32(r)?;
+        if version != Self::VERSION {
+            bail!(
+                "unsupported snapshot version {version}, expected {}",
+                Self::VERSION
+            );
+        }
+
+        let time = f32::from_bits(read_u32(r)?);
+        let flags = read_u32(r)?;
+        let count = read_u32(r)?;
+
+        let sims = (0..count)
+            .map(|_| {
+                let mut seed = [0; 8];
+                r.read_exact(&mut seed)?;
+                let seed = u64::from_le_bytes(seed);
+                let cursor = Vec2::new(f32::from_bits(read_u32(r)?), f32::from_bits(read_u32(r)?));
+
+                let len = read_u32(r)?;
+                let points = read_bytes(r, len as u64)?;
+
+                let target_size = (read_u32(r)?, read_u32(r)?);
+                let len = (target_size.0 as u64)
+                    .checked_mul(target_size.1 as u64)
+                    .and_then(|texels| texels.checked_mul(4))
+                    .ok_or_else(|| anyhow!("invalid target size {target_size:?}"))?;
+                let target = read_bytes(r, len)?
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+
+                Ok(Snapshot {
+                    seed,
+                    cursor,
+                    points,
+                    target_size,
+                    target,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { time, flags, sims })
+    }
+}
+
+fn write_u32(w: &mut impl Write, value: u32) -> Result<()> {
+    w.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+/// `len` bytes, the buffer only grows as far as the data goes,
+/// so a corrupt length fails instead of allocating it up front
+fn read_bytes(r: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    r.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        bail!("the snapshot ends early, expected {len} bytes");
+    }
+    Ok(bytes)
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+//
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// copy the particle buffer and the `R32Float` accumulation texture back to the CPU,
+/// both need the `COPY_SRC` usage
+pub fn read_back(
+    device: &Device,
+    queue: &Queue,
+    points: &Buffer,
+    target: &Texture,
+) -> Result<(Vec<u8>, Vec<f32>)> {
+    let (width, height) = (target.width(), target.height());
+    let bytes_per_row = (width * 4).next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let points_readback = device.create_buffer(&BufferDescriptor {
+        label: Some("snapshot points readback"),
+        size: points.size(),
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let target_readback = device.create_buffer(&BufferDescriptor {
+        label: Some("snapshot target readback"),
+        size: bytes_per_row as u64 * height as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("snapshot encoder"),
+    });
+    encoder.copy_buffer_to_buffer(points, 0, &points_readback, 0, points.size());
+    encoder.copy_texture_to_buffer(
+        target.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &target_readback,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        target.size(),
+    );
+    queue.submit([encoder.finish()]);
+
+    let (tx, rx) = mpsc::channel();
+    for buffer in [&points_readback, &target_readback] {
+        let tx = tx.clone();
+        buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| _ = tx.send(result));
+    }
+    device.poll(Maintain::Wait);
+
+    for _ in 0..2 {
+        rx.recv_timeout(TIMEOUT)
+            .map_err(|_| anyhow!("the snapshot readback timed out"))??;
+    }
+
+    let points = points_readback.slice(..).get_mapped_range().to_vec();
+    points_readback.unmap();
+
+    // drop the row padding
+    let target = target_readback
+        .slice(..)
+        .get_mapped_range()
+        .chunks_exact(bytes_per_row as usize)
+        .flat_map(|row| row[..width as usize * 4].chunks_exact(4))
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    target_readback.unmap();
+
+    Ok((points, target))
+}
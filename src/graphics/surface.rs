@@ -10,7 +10,7 @@ use wgpu::{
 };
 use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::settings::GraphicsSettings;
+use crate::settings::{BitDepth, ColorSpace, GraphicsSettings, SurfaceSettings};
 
 //
 
@@ -25,9 +25,14 @@ pub struct Surface {
     device: Arc<Device>,
 
     inner: SurfaceBuilder,
-    vsync: bool,
     format: TextureFormat,
-    // alpha_modes: Vec<CompositeAlphaMode>,
+    /// supported by the surface, in the order the adapter reports them
+    present_modes: Vec<PresentMode>,
+    present_mode: PresentMode,
+    frame_latency: u32,
+    alpha_mode: CompositeAlphaMode,
+    /// the window has no area, the surface is left unconfigured
+    suspended: bool,
 }
 
 //
@@ -46,20 +51,28 @@ impl SurfaceBuilder {
     pub fn build(self, settings: &GraphicsSettings, gpu: &Adapter, device: Arc<Device>) -> Surface {
         let SurfaceCapabilities {
             formats,
+            alpha_modes,
+            present_modes,
             ..
-            // alpha_modes,
-            // present_modes,
         } = self.surface.get_capabilities(gpu);
 
-        let format = *formats.first().expect("Surface is incompatible somehow");
+        let format = pick_format(&formats, &settings.surface);
+        let alpha_mode = pick_alpha_mode(&alpha_modes, &settings.surface);
+        tracing::info!("surface format {format:?} with {alpha_mode:?} alpha");
+
+        let present_mode = pick_present_mode(&present_modes, &settings.present_mode_preference());
+        tracing::info!("present mode {present_mode:?} (supported: {present_modes:?})");
 
         let mut surface = Surface {
             device,
 
             inner: self,
-            vsync: settings.vsync,
             format,
-            // alpha_modes,
+            present_modes,
+            present_mode,
+            frame_latency: settings.frame_latency.max(1),
+            alpha_mode,
+            suspended: false,
         };
 
         surface.configure(None);
@@ -72,13 +85,44 @@ impl Surface {
         self.format
     }
 
-    pub fn configure(&mut self, size: Option<(u32, u32)>) {
-        let present_mode = if self.vsync {
-            PresentMode::AutoVsync
-        } else {
-            PresentMode::AutoNoVsync
-        };
+    pub fn alpha_mode(&self) -> CompositeAlphaMode {
+        self.alpha_mode
+    }
 
+    pub fn suspended(&self) -> bool {
+        self.suspended
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    /// the first supported mode of `preference`, reconfigures if it changed
+    pub fn set_present_mode(&mut self, preference: &[PresentMode]) {
+        let present_mode = pick_present_mode(&self.present_modes, preference);
+        if present_mode == self.present_mode {
+            return;
+        }
+
+        tracing::info!("present mode {present_mode:?}");
+        self.present_mode = present_mode;
+        self.configure(None);
+    }
+
+    /// switch to the next supported present mode
+    pub fn cycle_present_mode(&mut self) {
+        let next = self
+            .present_modes
+            .iter()
+            .position(|mode| *mode == self.present_mode)
+            .map_or(0, |i| (i + 1) % self.present_modes.len());
+
+        if let Some(mode) = self.present_modes.get(next).copied() {
+            self.set_present_mode(&[mode]);
+        }
+    }
+
+    pub fn configure(&mut self, size: Option<(u32, u32)>) {
         /* let view_formats = if format.is_srgb() {
             vec![format]
         } else {
@@ -91,18 +135,18 @@ impl Surface {
             (width, height)
         });
 
-        let alpha_mode = CompositeAlphaMode::Auto;
-        /* if self
-            .alpha_modes
-            .contains(&CompositeAlphaMode::PostMultiplied)
-        {
-            alpha_mode = CompositeAlphaMode::PostMultiplied
-        } else if self
-            .alpha_modes
-            .contains(&CompositeAlphaMode::PreMultiplied)
-        {
-            alpha_mode = CompositeAlphaMode::PreMultiplied
-        }; */
+        // a zero size surface is invalid, wait for the window to come back
+        if width == 0 || height == 0 {
+            if !self.suspended {
+                tracing::debug!("surface suspended");
+            }
+            self.suspended = true;
+            return;
+        }
+        if self.suspended {
+            tracing::debug!("surface resumed");
+        }
+        self.suspended = false;
 
         // tracing::debug!("surface configured to {width}x{height}");
 
@@ -113,10 +157,10 @@ impl Surface {
                 format: self.format,
                 width,
                 height,
-                present_mode,
-                alpha_mode,
+                present_mode: self.present_mode,
+                alpha_mode: self.alpha_mode,
                 view_formats,
-                desired_maximum_frame_latency: 2,
+                desired_maximum_frame_latency: self.frame_latency,
             },
         );
     }
@@ -128,15 +172,11 @@ impl Surface {
         Ok(())
     }
 
-    pub fn acquire(&mut self) -> Result<SurfaceTexture> {
-        loop {
-            if let Some(texture) = self.try_acquire()? {
-                return Ok(texture);
-            }
+    pub fn try_acquire(&mut self) -> Result<Option<SurfaceTexture>> {
+        if self.suspended {
+            return Ok(None);
         }
-    }
 
-    pub fn try_acquire(&mut self) -> Result<Option<SurfaceTexture>> {
         match self.inner.surface.get_current_texture() {
             Ok(texture) => {
                 if texture.suboptimal {
@@ -149,9 +189,8 @@ impl Surface {
                 Ok(Some(texture))
             }
 
-            // TODO: autosave before
-            // closing
-            Err(SurfaceError::OutOfMemory) => panic!("Out of VRAM"),
+            // the device has to be recreated, see `Graphics::recover`
+            Err(SurfaceError::OutOfMemory) => Err(SurfaceError::OutOfMemory.into()),
 
             Err(SurfaceError::Timeout) => {
                 tracing::trace!("Surface timeout");
@@ -186,3 +225,66 @@ impl DerefMut for Surface {
         &mut self.inner
     }
 }
+
+/// the first supported mode of `preference`,
+/// Fifo is always supported
+fn pick_present_mode(present_modes: &[PresentMode], preference: &[PresentMode]) -> PresentMode {
+    let mode = preference
+        .iter()
+        .copied()
+        .find(|mode| present_modes.contains(mode))
+        .unwrap_or(PresentMode::Fifo);
+
+    for skipped in preference.iter().take_while(|skipped| **skipped != mode) {
+        tracing::debug!("present mode {skipped:?} is not supported");
+    }
+    mode
+}
+
+/// the deepest format allowed by `bit_depth`,
+/// then an 8 bit format matching `color_space`
+fn pick_format(formats: &[TextureFormat], s: &SurfaceSettings) -> TextureFormat {
+    let deep: &[TextureFormat] = match s.bit_depth {
+        BitDepth::Standard => &[],
+        BitDepth::TenBit => &[TextureFormat::Rgb10a2Unorm],
+        BitDepth::Hdr => &[TextureFormat::Rgba16Float, TextureFormat::Rgb10a2Unorm],
+    };
+    let srgb = s.color_space == ColorSpace::Srgb;
+
+    let format = deep
+        .iter()
+        .find(|format| formats.contains(format))
+        .or_else(|| formats.iter().find(|format| format.is_srgb() == srgb))
+        .or(formats.first())
+        .copied()
+        .expect("Surface is incompatible somehow");
+
+    if s.bit_depth != BitDepth::Standard && !deep.contains(&format) {
+        tracing::warn!("no {:?} surface format, using {format:?}", s.bit_depth);
+    }
+    format
+}
+
+/// the configured mode if the surface supports it,
+/// `Auto` prefers the transparent modes because the window is transparent
+fn pick_alpha_mode(alpha_modes: &[CompositeAlphaMode], s: &SurfaceSettings) -> CompositeAlphaMode {
+    let first = alpha_modes
+        .first()
+        .copied()
+        .unwrap_or(CompositeAlphaMode::Auto);
+
+    match s.alpha_mode.to_composite_alpha_mode() {
+        Some(mode) if alpha_modes.contains(&mode) => mode,
+        Some(mode) => {
+            tracing::warn!("the surface does not support {mode:?} alpha, using {first:?}");
+            first
+        }
+        None => [
+            CompositeAlphaMode::PreMultiplied,
+            CompositeAlphaMode::PostMultiplied,
+        ]
+        .into_iter()
+        .find(|mode| alpha_modes.contains(mode))
+        .unwrap_or(first),
+    }
+}
@@ -0,0 +1,164 @@
+use wgpu::*;
+
+//
+
+/// how the compute passes access the accumulation image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetMode {
+    /// a read-write `R32Float` storage texture
+    StorageTexture,
+    /// a storage buffer copied into the texture after the compute passes,
+    /// for adapters without read-write storage textures
+    StorageBuffer,
+}
+
+/// the accumulation image as a storage buffer,
+/// the rows are padded to the copy alignment
+pub struct TargetBuffer {
+    buffer: Buffer,
+    size: (u32, u32),
+    bytes_per_row: u32,
+}
+
+//
+
+impl TargetMode {
+    /// read-write storage textures if the device supports them and `allowed` is set
+    pub fn new(adapter: &Adapter, device: &Device, allowed: bool) -> Self {
+        let features = adapter.get_texture_format_features(TextureFormat::R32Float);
+
+        // without this feature only the WebGPU guaranteed
+        // format features are usable, which exclude read-write
+        let adapter_specific = device
+            .features()
+            .contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            || !adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(DownlevelFlags::WEBGPU_TEXTURE_FORMAT_SUPPORT);
+
+        let supported = adapter_specific
+            && features
+                .flags
+                .contains(TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+            && features
+                .allowed_usages
+                .contains(TextureUsages::STORAGE_BINDING);
+
+        if allowed && supported {
+            Self::StorageTexture
+        } else {
+            Self::StorageBuffer
+        }
+    }
+
+    /// the preprocessor define selecting the matching shader declarations
+    pub fn define(self) -> Option<&'static str> {
+        match self {
+            Self::StorageTexture => None,
+            Self::StorageBuffer => Some("TARGET_BUFFER"),
+        }
+    }
+
+    /// binding 0 of the shadow and update bind groups
+    pub fn binding_type(self) -> BindingType {
+        match self {
+            Self::StorageTexture => BindingType::StorageTexture {
+                access: StorageTextureAccess::ReadWrite,
+                format: TextureFormat::R32Float,
+                view_dimension: TextureViewDimension::D2,
+            },
+            Self::StorageBuffer => BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+        }
+    }
+
+    /// usages of the accumulation texture,
+    /// the render passes always read the texture and snapshots copy it
+    pub fn texture_usages(self) -> TextureUsages {
+        let usages = TextureUsages::TEXTURE_BINDING
+            | TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::COPY_SRC
+            | TextureUsages::COPY_DST;
+        match self {
+            Self::StorageTexture => usages | TextureUsages::STORAGE_BINDING,
+            Self::StorageBuffer => usages,
+        }
+    }
+
+    /// the most texels the accumulation image can have
+    pub fn max_texels(self, limits: &Limits) -> u64 {
+        match self {
+            Self::StorageTexture => u64::MAX,
+            Self::StorageBuffer => {
+                let padding = (COPY_BYTES_PER_ROW_ALIGNMENT / 4) as u64
+                    * limits.max_texture_dimension_2d as u64;
+                ((limits.max_storage_buffer_binding_size as u64)
+                    .saturating_sub(TargetBuffer::HEADER)
+                    / 4)
+                .saturating_sub(padding)
+            }
+        }
+    }
+}
+
+impl TargetBuffer {
+    /// `size`, `stride` and padding before the texels, matches `Accum` in `shader.wgsl`
+    const HEADER: u64 = 16;
+
+    pub fn new(device: &Device, queue: &Queue, (width, height): (u32, u32)) -> Self {
+        let bytes_per_row = (width * 4).next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("accumulation buffer"),
+            size: Self::HEADER + bytes_per_row as u64 * height as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let header = [width, height, bytes_per_row / 4, 0];
+        queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&header));
+
+        Self {
+            buffer,
+            size: (width, height),
+            bytes_per_row,
+        }
+    }
+
+    pub fn binding(&self) -> BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    /// copy the buffer into the texture the render passes read
+    pub fn resolve(&self, encoder: &mut CommandEncoder, texture: &Texture) {
+        encoder.copy_buffer_to_texture(self.image_copy(), texture.as_image_copy(), self.extent());
+    }
+
+    /// copy the texture into the buffer, after the texture was resampled
+    pub fn load(&self, encoder: &mut CommandEncoder, texture: &Texture) {
+        encoder.copy_texture_to_buffer(texture.as_image_copy(), self.image_copy(), self.extent());
+    }
+
+    fn image_copy(&self) -> ImageCopyBuffer<'_> {
+        ImageCopyBuffer {
+            buffer: &self.buffer,
+            layout: ImageDataLayout {
+                offset: Self::HEADER,
+                bytes_per_row: Some(self.bytes_per_row),
+                rows_per_image: None,
+            },
+        }
+    }
+
+    fn extent(&self) -> Extent3d {
+        Extent3d {
+            width: self.size.0,
+            height: self.size.1,
+            depth_or_array_layers: 1,
+        }
+    }
+}
@@ -0,0 +1,575 @@
+use std::{
+    env, fs,
+    mem::{offset_of, size_of},
+    path::Path,
+    process,
+};
+
+use glam::{IVec2, Vec2};
+use naga::{
+    front::wgsl,
+    valid::{Capabilities, ValidationFlags, Validator},
+    Module, StructMember, TypeInner,
+};
+use wgpu::Limits;
+
+use super::{
+    dispatch::WorkgroupSizes,
+    exposure::ExposurePush,
+    post::PostPush,
+    preprocess::{Preprocessed, Preprocessor},
+    region::{self, Rect, Region},
+    renderer::{BlitPush, UpdatePush},
+    resample::ResamplePush,
+    shaders::{preprocessor, EMBEDDED},
+    snapshot::{Snapshot, SnapshotFile},
+    variant::SimVariant,
+};
+
+//
+
+/// the wgsl struct `$name` in `$module` has the size and field offsets of `$rust`,
+/// every field has to be listed, in order
+macro_rules! assert_layout {
+    ($module:expr, $name:literal, $rust:ident { $($field:ident),* $(,)? }) => {{
+        let (members, span) = find_struct(&$module, $name);
+        let expected: &[(&str, u32)] = &[$((stringify!($field), offset_of!($rust, $field) as u32)),*];
+        let actual: Vec<_> = members
+            .iter()
+            .map(|member| (member.name.as_deref().unwrap_or_default(), member.offset))
+            .collect();
+
+        assert_eq!(actual, expected, "field offsets of {}", $name);
+        assert_eq!(span as usize, size_of::<$rust>(), "size of {}", $name);
+    }};
+}
+
+//
+
+#[test]
+fn embedded_shaders_validate() {
+    for (name, _) in EMBEDDED {
+        validate(name, load(name, []));
+    }
+}
+
+#[test]
+fn uniform_params_validate() {
+    // the fallback for devices without push constants
+    for (name, _) in EMBEDDED {
+        validate_with(name, load(name, ["PARAMS_UNIFORM"]), Capabilities::empty());
+    }
+}
+
+#[test]
+fn storage_buffer_target_validates() {
+    // the fallback for adapters without read-write storage textures
+    for (_, define) in SimVariant::DEFINES {
+        validate(
+            "shader.wgsl",
+            load("shader.wgsl", ["TARGET_BUFFER", define]),
+        );
+    }
+    validate_with(
+        "shader.wgsl",
+        load("shader.wgsl", ["TARGET_BUFFER", "PARAMS_UNIFORM"]),
+        Capabilities::empty(),
+    );
+}
+
+#[test]
+fn entry_points_exist() {
+    let expected: &[(&str, &[&str])] = &[
+        ("shader.wgsl", &["cs_main_update", "cs_main_shadow"]),
+        ("blit.wgsl", &["vs_main", "fs_main"]),
+        ("exposure.wgsl", &["cs_main_histogram", "cs_main_resolve"]),
+        ("resample.wgsl", &["vs_main", "fs_main"]),
+        (
+            "post.wgsl",
+            &[
+                "vs_main",
+                "fs_blur",
+                "fs_threshold",
+                "fs_downsample",
+                "fs_upsample",
+                "fs_composite",
+                "fs_vignette",
+                "fs_chromatic",
+                "fs_grain",
+                "fs_output",
+            ],
+        ),
+    ];
+
+    for (name, entry_points) in expected {
+        let module = validate(name, load(name, []));
+        for entry_point in *entry_points {
+            assert!(
+                module.entry_points.iter().any(|ep| ep.name == *entry_point),
+                "{name} has no entry point {entry_point}"
+            );
+        }
+    }
+}
+
+#[test]
+fn every_sim_variant_validates() {
+    let bits = SimVariant::DEFINES.len();
+    for combination in 0..1u32 << bits {
+        let flags = (0..bits)
+            .filter(|i| combination & 1 << i != 0)
+            .fold(0, |flags, i| flags | SimVariant::DEFINES[i].0);
+
+        let mut shader = load("shader.wgsl", SimVariant::from_flags(flags).defines());
+        WorkgroupSizes::new(&Limits::default()).apply(&mut shader.source);
+        validate("shader.wgsl", shader);
+    }
+}
+
+#[test]
+fn workgroup_sizes_validate() {
+    for limits in [
+        Limits::default(),
+        Limits::downlevel_defaults(),
+        Limits::downlevel_webgl2_defaults(),
+    ] {
+        let mut shader = load("shader.wgsl", []);
+        WorkgroupSizes::new(&limits).apply(&mut shader.source);
+        validate("shader.wgsl", shader);
+    }
+}
+
+#[test]
+fn push_layouts_match() {
+    let module = validate("shader.wgsl", load("shader.wgsl", []));
+    assert_layout!(module, "UpdatePush", UpdatePush { cursor, time, _pad });
+
+    let module = validate("blit.wgsl", load("blit.wgsl", []));
+    assert_layout!(
+        module,
+        "Push",
+        BlitPush {
+            offset,
+            scale,
+            flags,
+            _pad
+        }
+    );
+
+    let module = validate("exposure.wgsl", load("exposure.wgsl", []));
+    assert_layout!(
+        module,
+        "ExposurePush",
+        ExposurePush {
+            dt,
+            speed,
+            percentile,
+            manual,
+            flags
+        }
+    );
+
+    let module = validate("post.wgsl", load("post.wgsl", []));
+    assert_layout!(module, "Push", PostPush { texel, dir, params });
+
+    let module = validate("resample.wgsl", load("resample.wgsl", []));
+    assert_layout!(module, "Push", ResamplePush { scale, offset });
+}
+
+#[test]
+fn regions_cover_the_domain() {
+    // two 100x100 windows side by side, one of them lower on the desktop
+    let rects = [((-100, 0), (100, 100)), ((0, 50), (100, 100))];
+    let (size, regions) = Region::layout(&rects, (1, 1));
+    assert_eq!(size, (200, 150));
+    // y-up, the higher window is further from the bottom
+    assert_eq!(regions[0].offset, Vec2::new(0.0, 50.0 / 150.0));
+    assert_eq!(regions[0].scale, Vec2::new(0.5, 100.0 / 150.0));
+    assert_eq!(regions[1].offset, Vec2::new(0.5, 0.0));
+
+    let domain = Vec2::new(size.0 as f32, size.1 as f32);
+    let texel = |uv: Vec2| (uv * domain).floor().as_ivec2();
+    for (((x, y), (w, h)), region) in rects.iter().zip(regions.iter()) {
+        let window = Vec2::new(*w as f32, *h as f32);
+        for py in 0..*h as i32 {
+            for px in (0..*w as i32).step_by(7) {
+                let pixel = Vec2::new(px as f32, py as f32) + 0.5;
+
+                // the fragment of the blit, uv.y = 0 at the bottom of the window
+                let uv = Vec2::new(pixel.x / window.x, 1.0 - pixel.y / window.y);
+                let sampled = texel(region.to_target(uv));
+
+                // the update shader flips the y-down cursor into the y-up target
+                let cursor = region.to_domain(pixel, window);
+                let pulled = texel(Vec2::new(cursor.x, 1.0 - cursor.y));
+
+                // the desktop pixel, counted from the bottom left of the domain
+                let desktop = IVec2::new(x + px + 100, 149 - (y + py));
+
+                assert_eq!(sampled, desktop, "blit at {px},{py} of {region:?}");
+                assert_eq!(pulled, desktop, "cursor at {px},{py} of {region:?}");
+            }
+        }
+    }
+
+    let (size, regions) = Region::layout(&[((20, 30), (640, 480))], (1, 1));
+    assert_eq!(size, (640, 480));
+    assert_eq!(regions, [Region::FULL]);
+
+    // a domain kept larger than the windows while they are dragged
+    let (size, regions) = Region::layout(&[((20, 30), (640, 480))], (1280, 480));
+    assert_eq!(size, (1280, 480));
+    assert_eq!(regions[0].scale, Vec2::new(0.5, 1.0));
+}
+
+#[test]
+fn extra_windows_do_not_overlap() {
+    let overlap = |((ax, ay), (aw, ah)): Rect, ((bx, by), (bw, bh)): Rect| {
+        ax < bx + bw as i32 && bx < ax + aw as i32 && ay < by + bh as i32 && by < ay + ah as i32
+    };
+    let assert_apart = |placed: &[(Rect, Option<usize>)]| {
+        let rects: Vec<_> = placed.iter().map(|(rect, _)| *rect).collect();
+        for (i, a) in rects.iter().enumerate() {
+            for b in rects[i + 1..].iter() {
+                assert!(!overlap(*a, *b), "{a:?} overlaps {b:?}");
+            }
+        }
+
+        // the regions of the windows split the domain
+        let (size, regions) = Region::layout(&rects, (1, 1));
+        let domain = Vec2::new(size.0 as f32, size.1 as f32);
+        let texels: Vec<Rect> = regions
+            .iter()
+            .map(|region| {
+                let offset = (region.offset * domain).round().as_ivec2();
+                let size = (region.scale * domain).round().as_uvec2();
+                ((offset.x, offset.y), (size.x, size.y))
+            })
+            .collect();
+        for (i, a) in texels.iter().enumerate() {
+            for b in texels[i + 1..].iter() {
+                assert!(!overlap(*a, *b), "region {a:?} overlaps {b:?}");
+            }
+        }
+    };
+
+    let monitors = [((0, 0), (1920, 1080)), ((1920, 0), (1920, 1080))];
+
+    // windowed, one per monitor, then to the right of all of them
+    let placed = region::place(((100, 100), (1280, 720)), &monitors, 3, false);
+    assert_eq!(
+        placed,
+        [
+            (((100, 100), (1280, 720)), Some(0)),
+            (((1920, 0), (1280, 720)), Some(1)),
+            (((3200, 100), (1280, 720)), None),
+        ]
+    );
+    assert_apart(&placed);
+
+    // fullscreen on the second monitor, the extra one fills the first
+    let placed = region::place(monitors[1], &monitors, 2, true);
+    assert_eq!(placed, [(monitors[1], Some(1)), (monitors[0], Some(0))]);
+    assert_apart(&placed);
+
+    // no monitors known
+    let placed = region::place(((0, 0), (640, 480)), &[], 3, false);
+    assert_eq!(placed[2].0, ((1280, 0), (640, 480)));
+    assert_apart(&placed);
+}
+
+#[test]
+fn includes_are_pasted_once() {
+    static FILES: &[(&str, &str)] = &[
+        (
+            "main.wgsl",
+            "//!include \"common.wgsl\"\n//!include \"lib/a.wgsl\"\nmain",
+        ),
+        ("common.wgsl", "common"),
+        // relative to the including file
+        ("lib/a.wgsl", "//!include \"../common.wgsl\" \"b.wgsl\"\na"),
+        ("lib/b.wgsl", "b"),
+    ];
+    assert_eq!(
+        lines(&embedded(FILES, "main.wgsl").unwrap()),
+        ["common", "b", "a", "main"]
+    );
+}
+
+#[test]
+fn include_cycles_fail() {
+    static FILES: &[(&str, &str)] = &[
+        ("a.wgsl", "//!include \"b.wgsl\""),
+        ("b.wgsl", "//!include \"a.wgsl\""),
+    ];
+    let err = embedded(FILES, "a.wgsl").unwrap_err().to_string();
+    assert!(err.contains("include cycle"), "{err}");
+
+    static MISSING: &[(&str, &str)] = &[("a.wgsl", "//!include \"none.wgsl\"")];
+    assert!(embedded(MISSING, "a.wgsl").is_err());
+}
+
+#[test]
+fn search_paths_come_before_embedded() {
+    // an embedded file includes the ones on disk too
+    static FILES: &[(&str, &str)] = &[
+        (
+            "main.wgsl",
+            "//!include \"lib/common.wgsl\"\n//!include \"only_embedded.wgsl\"",
+        ),
+        (
+            "lib/common.wgsl",
+            "//!include \"other.wgsl\"\nembedded common",
+        ),
+        ("lib/other.wgsl", "embedded other"),
+        ("only_embedded.wgsl", "only embedded"),
+    ];
+
+    let dir = env::temp_dir().join(format!("flowfield-preprocess-{}", process::id()));
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(
+        dir.join("lib/common.wgsl"),
+        "//!include \"other.wgsl\"\ndisk common",
+    )
+    .unwrap();
+    fs::write(dir.join("lib/other.wgsl"), "disk other").unwrap();
+
+    let preprocessor = Preprocessor::new().search_path(&dir).embedded(FILES);
+    let shader = preprocessor.load("main.wgsl");
+    let other = preprocessor.load("lib/other.wgsl");
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        lines(&shader.unwrap()),
+        ["disk other", "disk common", "only embedded"]
+    );
+    assert_eq!(lines(&other.unwrap()), ["disk other"]);
+}
+
+#[test]
+fn conditionals_nest() {
+    static FILES: &[(&str, &str)] = &[(
+        "main.wgsl",
+        "\
+//!ifdef A
+a
+//!ifndef B
+not b
+//!ifdef C
+c
+//!else
+not c
+//!endif
+//!else
+b
+//!endif
+//!else
+not a
+//!ifdef B
+not a, b
+//!endif
+//!endif",
+    )];
+
+    let load = |defines: &[&str]| {
+        let shaders = defines
+            .iter()
+            .fold(Preprocessor::new().embedded(FILES), |shaders, define| {
+                shaders.define(*define)
+            });
+        lines(&shaders.load("main.wgsl").unwrap())
+    };
+    assert_eq!(load(&[]), ["not a"]);
+    assert_eq!(load(&["B"]), ["not a", "not a, b"]);
+    assert_eq!(load(&["A"]), ["a", "not b", "not c"]);
+    assert_eq!(load(&["A", "C"]), ["a", "not b", "c"]);
+    assert_eq!(load(&["A", "B", "C"]), ["a", "b"]);
+}
+
+#[test]
+fn unbalanced_conditionals_fail() {
+    static STRAY_ELSE: &[(&str, &str)] = &[("main.wgsl", "a\n//!else")];
+    static STRAY_ENDIF: &[(&str, &str)] = &[("main.wgsl", "//!ifdef A\n//!endif\n//!endif")];
+    static DUPLICATE_ELSE: &[(&str, &str)] =
+        &[("main.wgsl", "//!ifdef A\n//!else\n//!else\n//!endif")];
+    static UNTERMINATED: &[(&str, &str)] = &[("main.wgsl", "//!ifdef A\n//!ifdef B\n//!endif")];
+
+    for (files, expected) in [
+        (STRAY_ELSE, "main.wgsl:2: //!else without //!ifdef"),
+        (STRAY_ENDIF, "main.wgsl:3: //!endif without //!ifdef"),
+        (DUPLICATE_ELSE, "main.wgsl:3: duplicate //!else"),
+        (UNTERMINATED, "main.wgsl:1: unterminated conditional"),
+    ] {
+        let err = embedded(files, "main.wgsl").unwrap_err().to_string();
+        assert!(err.ends_with(expected), "{err}");
+    }
+}
+
+#[test]
+fn defines_and_undefs_apply_in_order() {
+    static FILES: &[(&str, &str)] = &[
+        (
+            "main.wgsl",
+            "\
+//!ifdef X
+x before
+//!endif
+//!include \"defines.wgsl\"
+//!ifdef X
+x
+//!endif
+//!ifdef Y
+y
+//!endif",
+        ),
+        // directives in skipped blocks are ignored
+        (
+            "defines.wgsl",
+            "//!define X\n//!undef Y\n//!ifdef NONE\n//!define Y\n//!endif",
+        ),
+    ];
+    let shaders = Preprocessor::new().embedded(FILES).define("Y");
+    assert_eq!(lines(&shaders.load("main.wgsl").unwrap()), ["x"]);
+}
+
+#[test]
+fn line_map_points_to_the_original_files() {
+    static FILES: &[(&str, &str)] = &[
+        ("main.wgsl", "//!include \"common.wgsl\"\nmain 2\nmain 3"),
+        (
+            "common.wgsl",
+            "common 1\n//!ifdef NONE\nskipped\n//!endif\ncommon 5",
+        ),
+    ];
+    let shader = embedded(FILES, "main.wgsl").unwrap();
+    assert_eq!(lines(&shader), ["common 1", "common 5", "main 2", "main 3"]);
+
+    let common = Path::new("<embedded>/common.wgsl");
+    let main = Path::new("<embedded>/main.wgsl");
+    assert_eq!(shader.line_map.lookup(2), Some((common, 5)));
+    assert_eq!(shader.line_map.lookup(4), Some((main, 3)));
+    assert_eq!(shader.line_map.lookup(5), None);
+
+    let rewritten = shader.line_map.rewrite(
+        "error at main.wgsl:2:7 and (main.wgsl:3:1), not in my_main.wgsl:3",
+        &["main.wgsl"],
+    );
+    assert_eq!(
+        rewritten,
+        "error at <embedded>/common.wgsl:5:7 and (<embedded>/main.wgsl:2:1), not in my_main.wgsl:3"
+    );
+}
+
+#[test]
+fn snapshots_round_trip() {
+    let file = SnapshotFile {
+        time: 12.5,
+        flags: 0b101,
+        sims: vec![Snapshot {
+            seed: 1234,
+            cursor: Vec2::new(0.25, -1.0),
+            points: (0..64).collect(),
+            target_size: (3, 2),
+            target: vec![0.0, 1.0, 2.5, -3.0, 1e9, f32::MIN_POSITIVE],
+        }],
+    };
+
+    let mut bytes = vec![];
+    file.write(&mut bytes).unwrap();
+    assert_eq!(SnapshotFile::read(&mut bytes.as_slice()).unwrap(), file);
+
+    // a newer version is rejected instead of misread
+    bytes[8] = 2;
+    assert!(SnapshotFile::read(&mut bytes.as_slice()).is_err());
+    assert!(SnapshotFile::read(&mut &b"not a snapshot"[..]).is_err());
+}
+
+#[test]
+fn corrupt_snapshot_sizes_fail() {
+    let header = |points: u32, target: (u32, u32)| {
+        let mut bytes = b"FLOWSNAP".to_vec();
+        for value in [1, 0, 0, 1] {
+            bytes.extend(u32::to_le_bytes(value));
+        }
+        bytes.extend([0; 16]);
+        for value in [points, target.0, target.1] {
+            bytes.extend(u32::to_le_bytes(value));
+        }
+        bytes
+    };
+
+    // errors out at the end of the data instead of allocating the sizes up front
+    let bytes = header(u32::MAX, (0, 0));
+    assert!(SnapshotFile::read(&mut bytes.as_slice()).is_err());
+    let mut bytes = header(0, (u32::MAX, u32::MAX));
+    bytes.extend([0; 64]);
+    assert!(SnapshotFile::read(&mut bytes.as_slice()).is_err());
+}
+
+//
+
+fn load(name: &str, defines: impl IntoIterator<Item = &'static str>) -> Preprocessed {
+    let shaders = defines
+        .into_iter()
+        .fold(preprocessor(None), |shaders, define| shaders.define(define));
+    shaders
+        .load(name)
+        .unwrap_or_else(|err| panic!("{name}: {err}"))
+}
+
+fn embedded(
+    files: &'static [(&'static str, &'static str)],
+    name: &str,
+) -> anyhow::Result<Preprocessed> {
+    Preprocessor::new().embedded(files).load(name)
+}
+
+/// the non-empty lines of the preprocessed source
+fn lines(shader: &Preprocessed) -> Vec<String> {
+    shader
+        .source
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn validate(name: &str, shader: Preprocessed) -> Module {
+    validate_with(name, shader, Capabilities::PUSH_CONSTANT)
+}
+
+/// parse and validate, the errors point to the original files
+fn validate_with(name: &str, shader: Preprocessed, capabilities: Capabilities) -> Module {
+    let rewrite = |err: String| shader.line_map.rewrite(&err, &[name]);
+
+    let module = wgsl::parse_str(&shader.source).unwrap_or_else(|err| {
+        panic!(
+            "{}",
+            rewrite(err.emit_to_string_with_path(&shader.source, name))
+        )
+    });
+
+    Validator::new(ValidationFlags::all(), capabilities)
+        .validate(&module)
+        .unwrap_or_else(|err| {
+            panic!(
+                "{}",
+                rewrite(err.emit_to_string_with_path(&shader.source, name))
+            )
+        });
+
+    module
+}
+
+fn find_struct<'a>(module: &'a Module, name: &str) -> (&'a [StructMember], u32) {
+    module
+        .types
+        .iter()
+        .find_map(|(_, ty)| match &ty.inner {
+            TypeInner::Struct { members, span } if ty.name.as_deref() == Some(name) => {
+                Some((members.as_slice(), *span))
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| panic!("no struct {name}"))
+}
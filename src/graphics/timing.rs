@@ -0,0 +1,166 @@
+use std::{
+    mem::size_of,
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use wgpu::*;
+
+//
+
+/// GPU durations of the main passes from timestamp queries,
+/// averaged and logged every few seconds
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve: Buffer,
+    readback: Buffer,
+    /// ns per timestamp tick
+    period: f64,
+
+    /// `Some` while `readback` is being mapped
+    mapping: Option<Receiver<Result<(), BufferAsyncError>>>,
+
+    totals: [f64; TimedPass::ALL.len()],
+    samples: u32,
+    last_log: Instant,
+}
+
+/// the passes with timestamps
+#[derive(Debug, Clone, Copy)]
+pub enum TimedPass {
+    Shadow,
+    Update,
+    Blit,
+}
+
+//
+
+impl TimedPass {
+    const ALL: [Self; 3] = [Self::Shadow, Self::Update, Self::Blit];
+
+    fn queries(self) -> (u32, u32) {
+        let begin = self as u32 * 2;
+        (begin, begin + 1)
+    }
+}
+
+impl GpuTimer {
+    const LOG_INTERVAL: Duration = Duration::from_secs(5);
+    const QUERIES: u32 = TimedPass::ALL.len() as u32 * 2;
+    const SIZE: u64 = Self::QUERIES as u64 * size_of::<u64>() as u64;
+
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("pass timestamps"),
+            ty: QueryType::Timestamp,
+            count: Self::QUERIES,
+        });
+
+        let resolve = device.create_buffer(&BufferDescriptor {
+            label: Some("timestamp resolve"),
+            size: Self::SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("timestamp readback"),
+            size: Self::SIZE,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            query_set,
+            resolve,
+            readback,
+            period: queue.get_timestamp_period() as f64,
+
+            mapping: None,
+
+            totals: <_>::default(),
+            samples: 0,
+            last_log: Instant::now(),
+        }
+    }
+
+    pub fn compute(&self, pass: TimedPass) -> ComputePassTimestampWrites<'_> {
+        let (begin, end) = pass.queries();
+        ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(begin),
+            end_of_pass_write_index: Some(end),
+        }
+    }
+
+    pub fn render(&self, pass: TimedPass) -> RenderPassTimestampWrites<'_> {
+        let (begin, end) = pass.queries();
+        RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(begin),
+            end_of_pass_write_index: Some(end),
+        }
+    }
+
+    /// copy this frame's timestamps out,
+    /// skipped while the last copy is still being read
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        if self.mapping.is_some() {
+            return;
+        }
+
+        encoder.resolve_query_set(&self.query_set, 0..Self::QUERIES, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, Self::SIZE);
+    }
+
+    /// after the submit, start reading the copied timestamps
+    /// or accumulate the ones read since the last frame
+    pub fn collect(&mut self, device: &Device) {
+        let Some(mapping) = self.mapping.as_ref() else {
+            let (tx, rx) = mpsc::channel();
+            self.readback
+                .slice(..)
+                .map_async(MapMode::Read, move |result| _ = tx.send(result));
+            self.mapping = Some(rx);
+            return;
+        };
+
+        device.poll(Maintain::Poll);
+        let result = match mapping.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err(BufferAsyncError),
+        };
+        self.mapping = None;
+
+        if let Err(err) = result {
+            tracing::warn!("Failed to read the GPU timestamps: {err}");
+            return;
+        }
+
+        {
+            let data = self.readback.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            for (i, pass) in TimedPass::ALL.into_iter().enumerate() {
+                let (begin, end) = pass.queries();
+                let ticks = timestamps[end as usize].saturating_sub(timestamps[begin as usize]);
+                self.totals[i] += ticks as f64 * self.period;
+            }
+        }
+        self.readback.unmap();
+        self.samples += 1;
+
+        if self.last_log.elapsed() >= Self::LOG_INTERVAL {
+            let mut line = String::new();
+            for (pass, total) in TimedPass::ALL.iter().zip(self.totals) {
+                let ms = total / self.samples as f64 / 1_000_000.0;
+                line.push_str(&format!(" {pass:?}: {ms:.3}ms"));
+            }
+            tracing::info!("GPU pass times:{line}");
+
+            self.totals = <_>::default();
+            self.samples = 0;
+            self.last_log = Instant::now();
+        }
+    }
+}
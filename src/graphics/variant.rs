@@ -0,0 +1,87 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use anyhow::Result;
+use wgpu::ComputePipeline;
+
+//
+
+/// a specialization of `shader.wgsl`,
+/// the runtime toggles are compiled in as preprocessor defines
+/// instead of branching on every invocation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SimVariant(u32);
+
+/// the compute pipelines of one [`SimVariant`]
+pub struct SimPipelines {
+    pub update: ComputePipeline,
+    pub shadow: ComputePipeline,
+}
+
+/// compiled variants, built the first time they are used
+#[derive(Default)]
+pub struct VariantCache {
+    /// `None` if the variant failed to compile, so it is not retried every frame
+    variants: HashMap<SimVariant, Option<SimPipelines>>,
+}
+
+//
+
+impl SimVariant {
+    /// runtime flag bits and the defines they enable
+    pub const DEFINES: [(u32, &'static str); 9] = [
+        (1, "FAST_FADE"),
+        (1 << 1, "SUBTRACT_FADE"),
+        (1 << 2, "HEAVY_POINTS"),
+        (1 << 3, "NO_CURSOR"),
+        (1 << 4, "HEAVY_CURSOR"),
+        (1 << 5, "NO_NOISE"),
+        (1 << 6, "HEAVY_NOISE"),
+        (1 << 7, "FREEZE_NOISE"),
+        (1 << 11, "CLEAR"),
+    ];
+
+    /// the variant for the runtime flags, other bits are ignored
+    pub fn from_flags(flags: u32) -> Self {
+        let mask = Self::DEFINES.iter().fold(0, |mask, (bit, _)| mask | bit);
+        Self(flags & mask)
+    }
+
+    pub fn defines(self) -> impl Iterator<Item = &'static str> {
+        Self::DEFINES
+            .into_iter()
+            .filter(move |(bit, _)| self.0 & bit != 0)
+            .map(|(_, define)| define)
+    }
+}
+
+impl VariantCache {
+    pub fn get(&self, variant: SimVariant) -> Option<&SimPipelines> {
+        self.variants.get(&variant)?.as_ref()
+    }
+
+    /// compile `variant` with `create` unless it was already tried,
+    /// returns true if it is usable
+    pub fn get_or_create(
+        &mut self,
+        variant: SimVariant,
+        create: impl FnOnce() -> Result<SimPipelines>,
+    ) -> bool {
+        match self.variants.entry(variant) {
+            Entry::Occupied(entry) => entry.get().is_some(),
+            Entry::Vacant(entry) => {
+                let pipelines = create()
+                    .map_err(|err| {
+                        tracing::error!("Failed to compile the shader variant {variant:?}:\n{err}")
+                    })
+                    .ok();
+                entry.insert(pipelines).is_some()
+            }
+        }
+    }
+
+    /// drop every variant and start over with `variant`
+    pub fn reset(&mut self, variant: SimVariant, pipelines: SimPipelines) {
+        self.variants.clear();
+        self.variants.insert(variant, Some(pipelines));
+    }
+}
@@ -0,0 +1,37 @@
+use glam::Vec2;
+use winit::window::WindowId;
+
+use super::{post::Post, region::Region, surface::Surface};
+
+//
+
+/// a window showing a [`Region`] of one of the simulations
+pub struct View {
+    pub surface: Surface,
+    pub post: Post,
+    /// the last non-zero window size
+    pub size: (u32, u32),
+    /// index of the simulation
+    pub sim: usize,
+    pub region: Region,
+}
+
+//
+
+impl View {
+    pub fn id(&self) -> WindowId {
+        self.surface.window.id()
+    }
+
+    /// the window position on the desktop, not available on Wayland
+    pub fn position(&self) -> Option<(i32, i32)> {
+        let position = self.surface.window.inner_position().ok()?;
+        Some((position.x, position.y))
+    }
+
+    /// window pixels to the uv of the whole domain
+    pub fn to_domain(&self, pixel: Vec2) -> Vec2 {
+        let size = Vec2::new(self.size.0 as f32, self.size.1 as f32);
+        self.region.to_domain(pixel, size)
+    }
+}
@@ -0,0 +1,52 @@
+use std::{
+    path::Path,
+    sync::mpsc::{self, Receiver},
+};
+
+use anyhow::Result;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+//
+
+/// watches the shader directory for modified `.wgsl` files
+pub struct ShaderWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+//
+
+impl ShaderWatcher {
+    pub fn new(dir: &Path) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            _ = tx.send(event);
+        })?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+
+        tracing::info!("watching {dir:?} for shader changes");
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// drains the pending events, true if any shader was created or modified
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) => {
+                    changed |= matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                        && event
+                            .paths
+                            .iter()
+                            .any(|path| path.extension().is_some_and(|ext| ext == "wgsl"));
+                }
+                Err(err) => tracing::warn!("shader watcher error: {err}"),
+            }
+        }
+        changed
+    }
+}
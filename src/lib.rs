@@ -0,0 +1,24 @@
+//! a particle flow field simulation on wgpu
+//!
+//! [`Flow`] runs one simulation on a device owned by the caller and draws it
+//! into any texture view, configured by a [`FlowConfig`] and the types in [`config`]
+//!
+//! the default `window` feature adds `graphics::Graphics`, the windowed frontend
+//! of the `flowfield` binary, configured by `settings::GlobalSettings`,
+//! without it the library has no winit or settings file dependencies
+
+pub mod config;
+pub mod graphics;
+#[cfg(feature = "window")]
+pub mod settings;
+
+pub use graphics::flow::{Flow, FlowConfig};
+
+//
+
+/// the toggles changed at runtime
+#[derive(Debug)]
+pub struct RuntimeSettings {
+    /// the bits of the F1-F12 keys, see [`graphics::variant::SimVariant`]
+    pub f: u32,
+}
@@ -2,27 +2,26 @@ use std::{env, sync::Arc};
 
 use glam::Vec2;
 use winit::{
-    dpi::LogicalSize,
-    event::{ElementState, Event, KeyEvent, MouseScrollDelta, StartCause, WindowEvent},
-    event_loop::{ControlFlow, EventLoopBuilder},
+    event::{ElementState, Event, KeyEvent, MouseScrollDelta, WindowEvent},
+    event_loop::EventLoopBuilder,
     keyboard::{KeyCode, PhysicalKey},
     platform::{wayland::EventLoopBuilderExtWayland, x11::EventLoopBuilderExtX11},
-    window::WindowBuilder,
 };
 
-use crate::settings::GlobalSettings;
-
-//
+use flowfield::{
+    graphics,
+    settings::{AdapterSettings, GlobalSettings, WindowMode},
+    RuntimeSettings,
+};
 
-pub mod graphics;
-pub mod settings;
+use crate::{cli::Args, scheduler::FrameScheduler, screensaver::Screensaver};
 
 //
 
-#[derive(Debug)]
-pub struct RuntimeSettings {
-    pub f: u32,
-}
+mod cli;
+mod scheduler;
+mod screensaver;
+mod window;
 
 //
 
@@ -41,9 +40,47 @@ async fn main() {
 
     tracing_subscriber::fmt::init();
 
-    let settings = GlobalSettings::load();
+    let args = Args::parse();
+
+    let mut settings = GlobalSettings::load();
     settings.autosave();
 
+    // command line overrides are not saved
+    if let Some(dir) = args.shader_dir {
+        settings.graphics.shader_dir = Some(dir);
+    }
+    if let Some(adapter) = args.adapter {
+        settings.graphics.adapter = match adapter.parse() {
+            Ok(index) => AdapterSettings {
+                index: Some(index),
+                ..<_>::default()
+            },
+            Err(_) => AdapterSettings {
+                name: Some(adapter),
+                ..<_>::default()
+            },
+        };
+    }
+
+    let mut screensaver = args.screensaver.then(|| {
+        if args.window_id.is_some() {
+            // XScreenSaver windows are X11 windows
+            settings.window.force_wayland = false;
+            settings.window.force_x11 = true;
+        } else {
+            settings.window.mode = match settings.screensaver.mode {
+                WindowMode::Windowed => WindowMode::Borderless,
+                mode => mode,
+            };
+        }
+        Screensaver::new(&settings.screensaver, args.window_id.is_some())
+    });
+
+    if args.list_adapters {
+        graphics::adapter::print_list(&settings.graphics);
+        return;
+    }
+
     tracing::debug!("{:#?}", &*settings);
 
     // use winit::platform::{wayland::*, x11::*};
@@ -58,35 +95,55 @@ async fn main() {
     .build()
     .expect("failed to create the event loop");
 
-    let window = WindowBuilder::new()
-        .with_title(settings.window.title.as_ref())
-        .with_inner_size(LogicalSize::new(
-            settings.window.resolution.0,
-            settings.window.resolution.1,
-        ))
-        .with_transparent(true)
-        /* .with_fullscreen(Some(Fullscreen::Exclusive(VideoMode::
-        ))) */
-        .with_visible(false)
-        .build(&events)
-        .expect("failed to open a window");
-
-    let window = Arc::new(window);
-
-    let mut graphics = graphics::Graphics::init(&settings, window.clone())
-        .await
-        .unwrap();
+    let windows: Vec<_> = window::build_all(&settings.window, &events, args.window_id)
+        .expect("failed to open a window")
+        .into_iter()
+        .map(Arc::new)
+        .collect();
+    if screensaver.is_some() {
+        for window in windows.iter() {
+            window.set_cursor_visible(false);
+        }
+    }
+
+    // its position and size are remembered
+    let primary = windows[0].clone();
 
+    // taken out for a moment when the device is recreated
+    let mut graphics = Some(
+        graphics::Graphics::init(&settings, windows.clone())
+            .await
+            .unwrap(),
+    );
+
+    let mut scheduler = FrameScheduler::new(
+        settings.graphics.max_fps,
+        settings.graphics.simulate_while_minimized,
+        windows.len(),
+    );
+    let mut global_settings = settings;
     let mut settings = RuntimeSettings { f: 0 };
 
-    window.set_visible(true);
+    if let Some(path) = args.load {
+        match graphics.as_mut().map(|gfx| gfx.load_snapshot(&path)) {
+            Some(Ok(flags)) => settings.f = flags,
+            Some(Err(err)) => tracing::error!("{err}"),
+            None => {}
+        }
+    }
+
+    for window in windows.iter() {
+        window.set_visible(true);
+    }
 
     events
         .run(move |event, target| {
-            target.set_control_flow(ControlFlow::Poll);
-
             // println!("{event:?}");
 
+            let Some(gfx) = graphics.as_mut() else {
+                return;
+            };
+
             match event {
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
@@ -94,7 +151,15 @@ async fn main() {
                 } => {
                     target.exit();
                 }
+                Event::WindowEvent { ref event, .. }
+                    if screensaver
+                        .as_mut()
+                        .is_some_and(|saver| saver.should_exit(event)) =>
+                {
+                    target.exit();
+                }
                 Event::WindowEvent {
+                    window_id,
                     event:
                         WindowEvent::KeyboardInput {
                             event:
@@ -126,6 +191,28 @@ async fn main() {
                         KeyCode::F11 => settings.f ^= 1 << 10,
                         KeyCode::F12 => settings.f ^= 1 << 11,
 
+                        KeyCode::KeyS => match global_settings.snapshot.path() {
+                            Some(path) => {
+                                if let Err(err) = gfx.save_snapshot(&path, settings.f) {
+                                    tracing::error!("Failed to save a snapshot: {err}");
+                                }
+                            }
+                            None => tracing::warn!("no snapshot path, set [snapshot] path"),
+                        },
+                        KeyCode::KeyL => match global_settings.snapshot.path() {
+                            Some(path) => match gfx.load_snapshot(&path) {
+                                Ok(flags) => settings.f = flags,
+                                Err(err) => tracing::error!("{err}"),
+                            },
+                            None => tracing::warn!("no snapshot path, set [snapshot] path"),
+                        },
+                        KeyCode::KeyV => gfx.cycle_present_mode(),
+                        KeyCode::KeyF => {
+                            if let Some(window) = windows.iter().find(|w| w.id() == window_id) {
+                                window::toggle_fullscreen(window, &global_settings.window)
+                            }
+                        }
+
                         KeyCode::Escape => {
                             target.exit();
                         }
@@ -151,28 +238,83 @@ async fn main() {
                         },
                     ..
                 } => {
-                    graphics.scrolled((x, y));
+                    gfx.scrolled((x, y));
                 }
                 Event::WindowEvent {
+                    window_id,
                     event: WindowEvent::Resized(s),
-                    ..
                 } => {
-                    graphics.resized((s.width, s.height));
+                    // minimized windows report 0x0 on most platforms
+                    let minimized = s.width == 0
+                        || s.height == 0
+                        || windows
+                            .iter()
+                            .find(|w| w.id() == window_id)
+                            .is_some_and(|w| w.is_minimized() == Some(true));
+                    scheduler.set_minimized(window_id, minimized);
+                    gfx.resized(window_id, (s.width, s.height));
+                }
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::Moved(_),
+                } => {
+                    gfx.moved(window_id);
+                }
+                Event::WindowEvent {
+                    window_id,
+                    event: WindowEvent::Occluded(occluded),
+                } => {
+                    scheduler.set_occluded(window_id, occluded);
                 }
                 Event::WindowEvent {
+                    window_id,
                     event: WindowEvent::CursorMoved { position, .. },
-                    ..
                 } => {
-                    graphics.cursor = Vec2::new(position.x as f32, position.y as f32);
+                    gfx.cursor_moved(window_id, Vec2::new(position.x as f32, position.y as f32));
                 }
 
-                Event::NewEvents(StartCause::Poll)
-                | Event::WindowEvent {
+                // offscreen frames, minimized windows get no redraws on every platform
+                Event::AboutToWait if scheduler.schedule(&windows, target) => {
+                    next_preset(&mut screensaver, &mut settings);
+                    gfx.simulate(&settings);
+                }
+                Event::LoopExiting => {
+                    // the screensaver window is always fullscreen,
+                    // only the primary window is remembered
+                    if screensaver.is_none() {
+                        window::remember(&primary, &mut global_settings);
+                    }
+                    global_settings.autosave();
+                }
+                // every window is drawn on the redraw of any visible one
+                Event::WindowEvent {
                     event: WindowEvent::RedrawRequested,
                     ..
-                } => graphics.frame(&settings),
+                } if scheduler.begin_frame() => {
+                    next_preset(&mut screensaver, &mut settings);
+                    gfx.frame(&settings);
+                }
                 _ => {}
             };
+
+            if graphics.as_ref().is_some_and(|gfx| gfx.needs_recovery()) {
+                let old = graphics.take().expect("graphics is always set here");
+                match old.recover(&settings) {
+                    Ok(gfx) => graphics = Some(gfx),
+                    // the settings and simulations were saved by `recover`
+                    Err(err) => {
+                        tracing::error!("{err:#}");
+                        target.exit();
+                    }
+                }
+            }
         })
         .unwrap();
 }
+
+/// the screensaver rotates the sim flags
+fn next_preset(screensaver: &mut Option<Screensaver>, settings: &mut RuntimeSettings) {
+    if let Some(preset) = screensaver.as_mut().and_then(Screensaver::next_preset) {
+        settings.f = preset;
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use winit::{
+    event_loop::{ControlFlow, EventLoopWindowTarget},
+    window::{Window, WindowId},
+};
+
+//
+
+/// decides when the next frame is drawn
+///
+/// presented frames are requested with [`Window::request_redraw`] on a visible window
+/// and drawn on its `RedrawRequested`, so the present mode of the surface does the pacing,
+/// offscreen frames are run on `AboutToWait` as minimized windows get no redraws on
+/// every platform
+#[derive(Debug)]
+pub struct FrameScheduler {
+    /// minimum time between frames, from the fps cap
+    interval: Option<Duration>,
+    last_frame: Instant,
+    /// number of open windows
+    windows: usize,
+    occluded: HashSet<WindowId>,
+    minimized: HashSet<WindowId>,
+    /// keep simulating while minimized
+    simulate_minimized: bool,
+}
+
+/// what to do with a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// simulate and present to the window
+    Present,
+    /// only advance the simulation, the window has nothing to draw to
+    Offscreen,
+}
+
+//
+
+impl FrameScheduler {
+    /// frame interval while simulating offscreen without an fps cap
+    const OFFSCREEN_INTERVAL: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    pub fn new(max_fps: Option<f32>, simulate_minimized: bool, windows: usize) -> Self {
+        let interval = max_fps
+            .filter(|fps| *fps > 0.0)
+            .map(|fps| Duration::from_secs_f32(1.0 / fps));
+
+        Self {
+            interval,
+            last_frame: Instant::now(),
+            windows,
+            occluded: <_>::default(),
+            minimized: <_>::default(),
+            simulate_minimized,
+        }
+    }
+
+    pub fn set_occluded(&mut self, window: WindowId, occluded: bool) {
+        set(&mut self.occluded, window, occluded);
+    }
+
+    pub fn set_minimized(&mut self, window: WindowId, minimized: bool) {
+        set(&mut self.minimized, window, minimized);
+    }
+
+    fn all_minimized(&self) -> bool {
+        self.minimized.len() >= self.windows
+    }
+
+    /// nothing of any window is visible, so nothing is drawn
+    pub fn paused(&self) -> bool {
+        self.occluded.union(&self.minimized).count() >= self.windows
+    }
+
+    /// the kind of the next frame, `None` if nothing is done at all
+    fn next_kind(&self) -> Option<FrameKind> {
+        if !self.paused() {
+            Some(FrameKind::Present)
+        } else if self.all_minimized() && self.simulate_minimized {
+            Some(FrameKind::Offscreen)
+        } else {
+            None
+        }
+    }
+
+    /// a window that gets redraws, any of them draws every window
+    fn presenting<'a>(&self, windows: &'a [Arc<Window>]) -> Option<&'a Window> {
+        let visible = |window: &&Arc<Window>| {
+            !self.occluded.contains(&window.id()) && !self.minimized.contains(&window.id())
+        };
+        windows.iter().find(visible).map(|window| &**window)
+    }
+
+    /// call on `AboutToWait`, requests the next presented frame once it is due
+    /// and sleeps until then, returns true if an offscreen frame is due now
+    pub fn schedule<T>(
+        &mut self,
+        windows: &[Arc<Window>],
+        target: &EventLoopWindowTarget<T>,
+    ) -> bool {
+        let Some(kind) = self.next_kind() else {
+            target.set_control_flow(ControlFlow::Wait);
+            return false;
+        };
+
+        // offscreen frames have no present mode pacing them
+        let interval = match kind {
+            FrameKind::Present => self.interval,
+            FrameKind::Offscreen => Some(self.interval.unwrap_or(Self::OFFSCREEN_INTERVAL)),
+        };
+
+        let now = Instant::now();
+        match interval.map(|interval| self.last_frame + interval) {
+            Some(due) if now < due => {
+                target.set_control_flow(ControlFlow::WaitUntil(due));
+                false
+            }
+            _ if kind == FrameKind::Offscreen => {
+                self.last_frame = now;
+                target.set_control_flow(ControlFlow::WaitUntil(now + interval.unwrap_or_default()));
+                true
+            }
+            _ => {
+                if let Some(window) = self.presenting(windows) {
+                    window.request_redraw();
+                }
+                target.set_control_flow(ControlFlow::Wait);
+                false
+            }
+        }
+    }
+
+    /// call on `RedrawRequested` of any window, whether to draw every window
+    pub fn begin_frame(&mut self) -> bool {
+        if self.next_kind() != Some(FrameKind::Present) {
+            return false;
+        }
+        self.last_frame = Instant::now();
+        true
+    }
+}
+
+fn set(windows: &mut HashSet<WindowId>, window: WindowId, value: bool) {
+    if value {
+        windows.insert(window);
+    } else {
+        windows.remove(&window);
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use winit::{dpi::PhysicalPosition, event::WindowEvent};
+
+use flowfield::settings::ScreensaverSettings;
+
+//
+
+/// input handling and preset rotation of the `--screensaver` mode
+#[derive(Debug)]
+pub struct Screensaver {
+    started: Instant,
+    grace_period: Duration,
+    /// XScreenSaver closes embedded screensavers itself
+    exit_on_input: bool,
+    /// where the cursor was first seen after the grace period
+    cursor: Option<PhysicalPosition<f64>>,
+
+    preset_interval: Option<Duration>,
+    last_preset: Option<Instant>,
+}
+
+//
+
+impl Screensaver {
+    /// cursor movement smaller than this is jitter, not input
+    const CURSOR_THRESHOLD: f64 = 8.0;
+
+    /// the sim variant flags (F1 - F8) the presets are picked from
+    const PRESET_FLAGS: u32 = 0xff;
+
+    pub fn new(s: &ScreensaverSettings, embedded: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            grace_period: Duration::from_secs_f32(s.grace_period.max(0.0)),
+            exit_on_input: !embedded,
+            cursor: None,
+
+            preset_interval: (s.preset_interval > 0.0)
+                .then(|| Duration::from_secs_f32(s.preset_interval)),
+            last_preset: None,
+        }
+    }
+
+    /// true if `event` is user input that should close the screensaver
+    pub fn should_exit(&mut self, event: &WindowEvent) -> bool {
+        if !self.exit_on_input || self.started.elapsed() < self.grace_period {
+            return false;
+        }
+
+        match event {
+            WindowEvent::KeyboardInput { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_) => true,
+            WindowEvent::CursorMoved { position, .. } => {
+                let origin = *self.cursor.get_or_insert(*position);
+                (position.x - origin.x).hypot(position.y - origin.y) > Self::CURSOR_THRESHOLD
+            }
+            _ => false,
+        }
+    }
+
+    /// new random sim flags at the start and then every `preset_interval`
+    pub fn next_preset(&mut self) -> Option<u32> {
+        let now = Instant::now();
+        let due = match (self.last_preset, self.preset_interval) {
+            (None, _) => true,
+            (Some(last), Some(interval)) => now.duration_since(last) >= interval,
+            (Some(_), None) => false,
+        };
+        if !due {
+            return None;
+        }
+
+        self.last_preset = Some(now);
+        let preset = rand::thread_rng().gen::<u32>() & Self::PRESET_FLAGS;
+        tracing::debug!("screensaver preset {preset:#010b}");
+        Some(preset)
+    }
+}
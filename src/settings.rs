@@ -2,6 +2,7 @@ use std::{
     fs::{self, File},
     io::{Read, Write},
     ops::{Deref, DerefMut},
+    path::PathBuf,
     sync::Arc,
 };
 
@@ -9,8 +10,15 @@ use anyhow::{anyhow, Result};
 use directories::ProjectDirs;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use toml_edit::{Document, Entry, Item, TableLike, Value};
-use wgpu::{Backends, PowerPreference};
+use toml_edit::{table, value, Array, Document, Entry, Item, TableLike, Value};
+use wgpu::{Backends, CompositeAlphaMode, DeviceType, PowerPreference};
+
+use crate::graphics::flow::FlowConfig;
+
+pub use crate::config::{
+    BloomSettings, BlurSettings, ChromaticSettings, ExposureSettings, GrainSettings, PostPass,
+    PostSettings, ResizeMode, VignetteSettings,
+};
 
 //
 
@@ -32,24 +40,159 @@ pub struct GlobalSettings {
 pub struct SettingsInner {
     pub window: WindowSettings,
     pub graphics: GraphicsSettings,
+    pub screensaver: ScreensaverSettings,
+    pub snapshot: SnapshotSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct WindowSettings {
     pub resolution: (u32, u32),
+    /// remembered from the last run, `None` lets the OS place the window
+    pub position: Option<(i32, i32)>,
+    pub mode: WindowMode,
+    pub monitor: MonitorSettings,
+    pub video_mode: VideoModeSettings,
+    /// windows to open, ignored with `per_monitor`
+    pub count: u32,
+    /// one fullscreen window on every monitor
+    pub per_monitor: bool,
+    /// every window shows its part of one simulation,
+    /// instead of each running its own
+    pub shared_simulation: bool,
     pub title: Arc<str>,
     pub force_wayland: bool,
     pub force_x11: bool,
 }
 
+/// where the S and L keys save and load the simulation state
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotSettings {
+    /// `None` is `snapshot.flow` in the data directory
+    pub path: Option<PathBuf>,
+}
+
+/// the `--screensaver` run mode
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreensaverSettings {
+    /// fullscreen mode on `window.monitor`, `Windowed` is borderless
+    pub mode: WindowMode,
+    /// seconds after the start where input doesn't exit
+    pub grace_period: f32,
+    /// seconds between random presets, 0 keeps the first one
+    pub preset_interval: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    /// a window covering the whole monitor
+    Borderless,
+    /// takes over the monitor with `video_mode`
+    Exclusive,
+}
+
+/// picks the fullscreen monitor, every set field has to match
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorSettings {
+    pub index: Option<usize>,
+    /// case insensitive part of the monitor name
+    pub name: Option<String>,
+}
+
+/// picks the exclusive fullscreen video mode
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoModeSettings {
+    /// `None` uses the current resolution of the monitor
+    pub resolution: Option<(u32, u32)>,
+    /// in Hz, `None` uses the highest rate
+    pub refresh_rate: Option<u32>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct GraphicsSettings {
     pub allowed_backends: GraphicsBackends,
     pub gpu_preference: GpuPreference,
+    pub adapter: AdapterSettings,
     pub force_software_rendering: bool,
     pub vsync: bool,
+    /// tried in order, empty uses `vsync`
+    pub present_modes: Vec<PresentMode>,
+    pub frame_latency: u32,
+    pub max_fps: Option<f32>,
+    pub simulate_while_minimized: bool,
+    pub render_scale: f32,
+    /// the initial particles, `None` picks a random one
+    pub seed: Option<u64>,
+    pub resize_mode: ResizeMode,
+    pub shader_dir: Option<PathBuf>,
+    pub shader_hot_reload: bool,
+    pub push_constants: bool,
+    pub storage_textures: bool,
+    pub gpu_timings: bool,
+    pub surface: SurfaceSettings,
+    pub exposure: ExposureSettings,
+    pub post: PostSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresentMode {
+    /// vsync, always supported
+    Fifo,
+    /// vsync, but late frames tear instead of waiting
+    FifoRelaxed,
+    /// no tearing and no waiting, the newest frame is shown
+    Mailbox,
+    /// no vsync, tears
+    Immediate,
+}
+
+/// how the final image is handed to the window
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SurfaceSettings {
+    pub color_space: ColorSpace,
+    pub bit_depth: BitDepth,
+    pub alpha_mode: AlphaMode,
+}
+
+/// the kind of 8 bit surface format to prefer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorSpace {
+    /// the GPU encodes the output to sRGB
+    #[default]
+    Srgb,
+    /// a plain UNORM format, the output shader encodes to sRGB
+    Linear,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BitDepth {
+    /// 8 bits per channel
+    #[default]
+    Standard,
+    /// 10 bits per channel, less banding in the dark gradients
+    TenBit,
+    /// 16 bit float, extended range on HDR displays,
+    /// falls back to TenBit
+    Hdr,
+}
+
+/// how the window is blended with what is behind it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlphaMode {
+    /// transparent if the surface supports it
+    #[default]
+    Auto,
+    Opaque,
+    PreMultiplied,
+    PostMultiplied,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -71,6 +214,26 @@ pub enum GpuPreference {
     LowPower,
 }
 
+/// picks a specific adapter, every set field has to match
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdapterSettings {
+    /// position in `--list-adapters`
+    pub index: Option<usize>,
+    /// case insensitive part of the adapter name
+    pub name: Option<String>,
+    pub kind: Option<AdapterKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdapterKind {
+    DiscreteGpu,
+    IntegratedGpu,
+    VirtualGpu,
+    Cpu,
+    Other,
+}
+
 //
 
 impl GlobalSettings {
@@ -92,7 +255,7 @@ impl GlobalSettings {
 
         const DEFAULT: &str = include_str!("./settings.toml");
 
-        let document: Document = if file.metadata()?.len() == 0 {
+        let mut document: Document = if file.metadata()?.len() == 0 {
             file.write_all(DEFAULT.as_bytes())?;
 
             DEFAULT
@@ -106,6 +269,8 @@ impl GlobalSettings {
                 .map_err(|err| anyhow!("config is invalid:\n{err}"))?
         };
 
+        Self::migrate(&mut document);
+
         /* file.flush()?;
 
         let modified = file.metadata().ok().and_then(|meta| meta.modified().ok()); */
@@ -128,6 +293,59 @@ impl GlobalSettings {
         })
     }
 
+    /// settings files from before the `[graphics]` table had the graphics keys
+    /// in `[window]`, where they were ignored, move them to where they are read,
+    /// the next [`Self::autosave`] writes the new layout
+    fn migrate(document: &mut Document) {
+        const MOVED: &[&str] = &[
+            "gpu_preference",
+            "force_software_rendering",
+            "vsync",
+            "allowed_backends",
+        ];
+
+        let Some(window) = document.get_mut("window").and_then(Item::as_table_like_mut) else {
+            return;
+        };
+        let moved: Vec<_> = MOVED
+            .iter()
+            .filter_map(|key| Some((*key, window.remove(key)?)))
+            .collect();
+        if moved.is_empty() {
+            return;
+        }
+
+        let graphics = document.entry("graphics").or_insert(table());
+        let Some(graphics) = graphics.as_table_like_mut() else {
+            tracing::error!(
+                "[graphics] is not a table, dropping the old window.* graphics settings"
+            );
+            return;
+        };
+        for (key, item) in moved {
+            tracing::info!("moving the setting window.{key} to graphics.{key}");
+            if !graphics.contains_key(key) {
+                graphics.insert(key, item);
+            }
+        }
+    }
+
+    /// keep the windowed size and position in the settings document,
+    /// written by the next [`Self::autosave`]
+    pub fn remember_window(&mut self, resolution: (u32, u32), position: Option<(i32, i32)>) {
+        self.inner.window.resolution = resolution;
+        self.inner.window.position = position.or(self.inner.window.position);
+
+        let Some(document) = self.document.as_mut() else {
+            return;
+        };
+        let window = &mut document["window"];
+        window["resolution"] = value(Array::from_iter([resolution.0 as i64, resolution.1 as i64]));
+        if let Some((x, y)) = position {
+            window["position"] = value(Array::from_iter([x as i64, y as i64]));
+        }
+    }
+
     pub fn autosave(&self) {
         if let Some(document) = self.document.as_ref() {
             self.save(document)
@@ -231,6 +449,7 @@ impl GlobalSettings {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(config)?)
     }
 }
@@ -239,6 +458,13 @@ impl Default for WindowSettings {
     fn default() -> Self {
         Self {
             resolution: (1280, 720),
+            position: None,
+            mode: <_>::default(),
+            monitor: <_>::default(),
+            video_mode: <_>::default(),
+            count: 1,
+            per_monitor: false,
+            shared_simulation: true,
             title: "flowfield".into(),
             force_wayland: false,
             force_x11: false,
@@ -246,13 +472,49 @@ impl Default for WindowSettings {
     }
 }
 
+impl SnapshotSettings {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.clone().or_else(|| {
+            PROJECT_DIRS
+                .as_ref()
+                .map(|dirs| dirs.data_dir().join("snapshot.flow"))
+        })
+    }
+}
+
+impl Default for ScreensaverSettings {
+    fn default() -> Self {
+        Self {
+            mode: WindowMode::Borderless,
+            grace_period: 2.0,
+            preset_interval: 60.0,
+        }
+    }
+}
+
 impl Default for GraphicsSettings {
     fn default() -> Self {
         Self {
             allowed_backends: <_>::default(),
             gpu_preference: <_>::default(),
+            adapter: <_>::default(),
             force_software_rendering: false,
             vsync: true,
+            present_modes: vec![],
+            frame_latency: 2,
+            max_fps: None,
+            simulate_while_minimized: false,
+            render_scale: 1.0,
+            seed: None,
+            resize_mode: <_>::default(),
+            shader_dir: None,
+            shader_hot_reload: true,
+            push_constants: true,
+            storage_textures: true,
+            gpu_timings: false,
+            surface: <_>::default(),
+            exposure: <_>::default(),
+            post: <_>::default(),
         }
     }
 }
@@ -278,7 +540,8 @@ impl GraphicsBackends {
         backends.set(Backends::VULKAN, self.vulkan);
         backends.set(Backends::GL, self.gl);
         backends.set(Backends::METAL, self.metal);
-        backends.set(Backends::DX12, self.dx12);
+        // wgpu dropped DX11, DX12 also runs on DX11 class GPUs
+        backends.set(Backends::DX12, self.dx12 || self.dx11);
         backends.set(Backends::BROWSER_WEBGPU, self.webgpu);
 
         backends
@@ -294,6 +557,80 @@ impl GpuPreference {
     }
 }
 
+impl AdapterKind {
+    pub fn to_device_type(self) -> DeviceType {
+        match self {
+            AdapterKind::DiscreteGpu => DeviceType::DiscreteGpu,
+            AdapterKind::IntegratedGpu => DeviceType::IntegratedGpu,
+            AdapterKind::VirtualGpu => DeviceType::VirtualGpu,
+            AdapterKind::Cpu => DeviceType::Cpu,
+            AdapterKind::Other => DeviceType::Other,
+        }
+    }
+}
+
+impl GraphicsSettings {
+    /// the parts the simulation and its pipelines need
+    pub fn to_flow_config(&self) -> FlowConfig {
+        FlowConfig {
+            push_constants: self.push_constants,
+            storage_textures: self.storage_textures,
+            shader_dir: self.shader_dir.clone(),
+            render_scale: self.render_scale,
+            seed: self.seed,
+            resize_mode: self.resize_mode,
+            exposure: self.exposure,
+            post: self.post.clone(),
+            // the alpha mode of each window comes from its surface
+            ..<_>::default()
+        }
+    }
+
+    /// the present modes to try in order
+    pub fn present_mode_preference(&self) -> Vec<wgpu::PresentMode> {
+        if !self.present_modes.is_empty() {
+            return self
+                .present_modes
+                .iter()
+                .map(|mode| mode.to_present_mode())
+                .collect();
+        }
+
+        if self.vsync {
+            vec![wgpu::PresentMode::FifoRelaxed, wgpu::PresentMode::Fifo]
+        } else {
+            vec![
+                wgpu::PresentMode::Immediate,
+                wgpu::PresentMode::Mailbox,
+                wgpu::PresentMode::Fifo,
+            ]
+        }
+    }
+}
+
+impl PresentMode {
+    pub fn to_present_mode(self) -> wgpu::PresentMode {
+        match self {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+impl AlphaMode {
+    /// `None` for `Auto`
+    pub fn to_composite_alpha_mode(self) -> Option<CompositeAlphaMode> {
+        match self {
+            AlphaMode::Auto => None,
+            AlphaMode::Opaque => Some(CompositeAlphaMode::Opaque),
+            AlphaMode::PreMultiplied => Some(CompositeAlphaMode::PreMultiplied),
+            AlphaMode::PostMultiplied => Some(CompositeAlphaMode::PostMultiplied),
+        }
+    }
+}
+
 impl Deref for GlobalSettings {
     type Target = SettingsInner;
 
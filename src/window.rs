@@ -0,0 +1,289 @@
+use std::cmp::Reverse;
+
+use anyhow::Result;
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    event_loop::EventLoopWindowTarget,
+    monitor::{MonitorHandle, VideoMode},
+    platform::x11::{WindowBuilderExtX11, XWindow},
+    window::{Fullscreen, Window, WindowBuilder},
+};
+
+use x11rb::protocol::xproto::ConnectionExt;
+
+use flowfield::{
+    graphics::region::{self, Rect},
+    settings::{GlobalSettings, MonitorSettings, VideoModeSettings, WindowMode, WindowSettings},
+};
+
+//
+
+/// open the (hidden) windows from the settings, the first one is the primary,
+/// or a single child covering the X11 window `parent`
+pub fn build_all<T>(
+    s: &WindowSettings,
+    target: &EventLoopWindowTarget<T>,
+    parent: Option<XWindow>,
+) -> Result<Vec<Window>> {
+    for (i, monitor) in target.available_monitors().enumerate() {
+        tracing::debug!("monitor {}", describe(i, &monitor));
+    }
+
+    if parent.is_some() {
+        return Ok(vec![build(s, target, parent)?]);
+    }
+
+    if s.per_monitor {
+        let mode = match s.mode {
+            WindowMode::Windowed => WindowMode::Borderless,
+            mode => mode,
+        };
+        let windows = target
+            .available_monitors()
+            .map(|monitor| {
+                Ok(builder(s)
+                    .with_fullscreen(fullscreen_on(s, mode, Some(monitor)))
+                    .build(target)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if !windows.is_empty() {
+            return Ok(windows);
+        }
+        tracing::warn!("no monitors found, opening one window");
+    }
+
+    // only the primary window is placed at the remembered position,
+    // the extra ones go to the other monitors so their regions don't overlap
+    let mut windows = vec![build(s, target, None)?];
+    if s.count <= 1 {
+        return Ok(windows);
+    }
+
+    let monitors: Vec<_> = target.available_monitors().collect();
+    let rects: Vec<_> = monitors.iter().map(rect).collect();
+    let fill = s.mode != WindowMode::Windowed;
+    let primary = target.primary_monitor();
+    let first = if fill {
+        pick_monitor(&s.monitor, monitors.iter().cloned(), primary).map(|monitor| rect(&monitor))
+    } else {
+        let scale = primary.as_ref().map_or(1.0, MonitorHandle::scale_factor);
+        let size = LogicalSize::new(s.resolution.0, s.resolution.1).to_physical(scale);
+        let position = s
+            .position
+            .or_else(|| primary.as_ref().map(|m| rect(m).0))
+            .unwrap_or_default();
+        Some((position, (size.width, size.height)))
+    };
+    let first = first.unwrap_or(((0, 0), (s.resolution.0, s.resolution.1)));
+
+    for ((position, _), monitor) in region::place(first, &rects, s.count as usize, fill)
+        .into_iter()
+        .skip(1)
+    {
+        let builder = match monitor {
+            Some(i) if fill => {
+                builder(s).with_fullscreen(fullscreen_on(s, s.mode, Some(monitors[i].clone())))
+            }
+            _ => builder(s).with_position(PhysicalPosition::new(position.0, position.1)),
+        };
+        windows.push(builder.build(target)?);
+    }
+    Ok(windows)
+}
+
+/// the position and size of `monitor` on the desktop
+fn rect(monitor: &MonitorHandle) -> Rect {
+    let position = monitor.position();
+    let size = monitor.size();
+    ((position.x, position.y), (size.width, size.height))
+}
+
+/// open the (hidden) window in the configured mode,
+/// or as a child covering the X11 window `parent`
+pub fn build<T>(
+    s: &WindowSettings,
+    target: &EventLoopWindowTarget<T>,
+    parent: Option<XWindow>,
+) -> Result<Window> {
+    let mut builder = builder(s).with_fullscreen(fullscreen(
+        s,
+        s.mode,
+        target.available_monitors(),
+        target.primary_monitor(),
+    ));
+
+    if let Some(parent) = parent {
+        // the parent is a fullscreen XScreenSaver window on any screen,
+        // or the small preview in its settings
+        let size = parent_size(parent).unwrap_or_else(|err| {
+            tracing::warn!("Failed to get the size of the window {parent:#x}: {err}");
+            target
+                .primary_monitor()
+                .map(|monitor| monitor.size())
+                .unwrap_or_else(|| {
+                    LogicalSize::new(s.resolution.0, s.resolution.1).to_physical(1.0)
+                })
+        });
+        builder = builder
+            .with_embed_parent_window(parent)
+            .with_fullscreen(None)
+            .with_inner_size(size)
+            .with_position(PhysicalPosition::new(0, 0));
+    } else if let Some((x, y)) = s.position {
+        builder = builder.with_position(PhysicalPosition::new(x, y));
+    }
+
+    Ok(builder.build(target)?)
+}
+
+/// the size of the X11 window `parent`
+fn parent_size(parent: XWindow) -> Result<PhysicalSize<u32>> {
+    let (conn, _) = x11rb::connect(None)?;
+    let geometry = conn.get_geometry(parent)?.reply()?;
+    Ok(PhysicalSize::new(
+        geometry.width as u32,
+        geometry.height as u32,
+    ))
+}
+
+fn builder(s: &WindowSettings) -> WindowBuilder {
+    WindowBuilder::new()
+        .with_title(s.title.as_ref())
+        .with_inner_size(LogicalSize::new(s.resolution.0, s.resolution.1))
+        .with_transparent(true)
+        .with_visible(false)
+}
+
+/// switch between windowed and the configured fullscreen mode,
+/// borderless if the configured mode is windowed
+pub fn toggle_fullscreen(window: &Window, s: &WindowSettings) {
+    if window.fullscreen().is_some() {
+        window.set_fullscreen(None);
+        return;
+    }
+
+    let mode = match s.mode {
+        WindowMode::Windowed => WindowMode::Borderless,
+        mode => mode,
+    };
+    window.set_fullscreen(fullscreen(
+        s,
+        mode,
+        window.available_monitors(),
+        window.current_monitor(),
+    ));
+}
+
+/// store the size and position of a windowed window in the settings,
+/// fullscreen and minimized windows keep the last windowed ones
+pub fn remember(window: &Window, settings: &mut GlobalSettings) {
+    if window.fullscreen().is_some() || window.is_minimized() == Some(true) {
+        return;
+    }
+
+    let size: LogicalSize<u32> = window.inner_size().to_logical(window.scale_factor());
+    if size.width == 0 || size.height == 0 {
+        return;
+    }
+
+    // not available on Wayland
+    let position = window.outer_position().ok().map(|p| (p.x, p.y));
+    settings.remember_window((size.width, size.height), position);
+}
+
+fn fullscreen(
+    s: &WindowSettings,
+    mode: WindowMode,
+    monitors: impl Iterator<Item = MonitorHandle>,
+    current: Option<MonitorHandle>,
+) -> Option<Fullscreen> {
+    if mode == WindowMode::Windowed {
+        return None;
+    }
+
+    fullscreen_on(s, mode, pick_monitor(&s.monitor, monitors, current))
+}
+
+fn fullscreen_on(
+    s: &WindowSettings,
+    mode: WindowMode,
+    monitor: Option<MonitorHandle>,
+) -> Option<Fullscreen> {
+    if mode == WindowMode::Windowed {
+        return None;
+    }
+
+    if mode == WindowMode::Borderless {
+        return Some(Fullscreen::Borderless(monitor));
+    }
+
+    match monitor
+        .as_ref()
+        .and_then(|monitor| pick_video_mode(&s.video_mode, monitor))
+    {
+        Some(video_mode) => {
+            tracing::info!("exclusive fullscreen with {video_mode}");
+            Some(Fullscreen::Exclusive(video_mode))
+        }
+        None => {
+            tracing::warn!("no video mode for exclusive fullscreen, using borderless");
+            Some(Fullscreen::Borderless(monitor))
+        }
+    }
+}
+
+/// the first monitor matching every set field, or `current`
+fn pick_monitor(
+    s: &MonitorSettings,
+    monitors: impl Iterator<Item = MonitorHandle>,
+    current: Option<MonitorHandle>,
+) -> Option<MonitorHandle> {
+    if s.index.is_none() && s.name.is_none() {
+        return current;
+    }
+
+    let name = s.name.as_ref().map(|name| name.to_lowercase());
+    let found = monitors
+        .enumerate()
+        .find(|(i, monitor)| {
+            s.index.is_none_or(|index| index == *i)
+                && name.as_ref().is_none_or(|name| {
+                    monitor
+                        .name()
+                        .is_some_and(|n| n.to_lowercase().contains(name))
+                })
+        })
+        .map(|(_, monitor)| monitor);
+
+    if found.is_none() {
+        tracing::warn!("no monitor matches {s:?}, using the current one");
+    }
+    found.or(current)
+}
+
+/// the closest size, then the closest (or highest) refresh rate,
+/// then the deepest color
+fn pick_video_mode(s: &VideoModeSettings, monitor: &MonitorHandle) -> Option<VideoMode> {
+    let size = monitor.size();
+    let (width, height) = s.resolution.unwrap_or((size.width, size.height));
+    let refresh_rate = s.refresh_rate.map(|hz| hz * 1000);
+
+    monitor.video_modes().min_by_key(|mode| {
+        let size = mode.size();
+        let size_diff = size.width.abs_diff(width) + size.height.abs_diff(height);
+        let rate = mode.refresh_rate_millihertz();
+        let rate_diff = refresh_rate.map_or(u32::MAX - rate, |target| rate.abs_diff(target));
+        (size_diff, rate_diff, Reverse(mode.bit_depth()))
+    })
+}
+
+fn describe(index: usize, monitor: &MonitorHandle) -> String {
+    let size = monitor.size();
+    format!(
+        "{index}: {} ({}x{} @ {:.1} Hz)",
+        monitor.name().as_deref().unwrap_or("unknown"),
+        size.width,
+        size.height,
+        monitor.refresh_rate_millihertz().unwrap_or(0) as f32 / 1000.0,
+    )
+}